@@ -20,3 +20,11 @@ export function on_request(cmd, handler) {
         (document.frame.handlers[cmd] || defaultHandler)(cmd, msg.params)
     }
 }
+
+export function sleep(ms) {
+    return new Promise(resolve => setTimeout(resolve, ms))
+}
+
+export function new_regexp(pattern, flags) {
+    return new RegExp(pattern, flags)
+}
@@ -0,0 +1,250 @@
+use crate::ZeroFrameError as Error;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+const ADDRESS_VERSION: u8 = 0x00;
+const ADDRESS_LENGTH: usize = 21;
+
+/// Check a bitcoin-style address: Base58Check encoded version byte 0 and a 20 byte hash
+fn validate_address(address: &str) -> Result<(), Error> {
+  let decoded = bs58::decode(address)
+    .with_check(Some(ADDRESS_VERSION))
+    .into_vec()
+    .map_err(|err| Error::InvalidAddress(format!("{}: {}", address, err)))?;
+  if decoded.len() != ADDRESS_LENGTH {
+    return Err(Error::InvalidAddress(format!(
+      "{}: invalid length",
+      address
+    )));
+  }
+  Ok(())
+}
+
+/// Check a Namecoin domain as resolved by ZeroNet, like zeroid.bit
+fn validate_domain(domain: &str) -> Result<(), Error> {
+  let name = domain.strip_suffix(".bit").unwrap_or_default();
+  let valid = !name.is_empty()
+    && name.starts_with(|c: char| c.is_ascii_alphanumeric())
+    && name
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.');
+  if valid {
+    Ok(())
+  } else {
+    Err(Error::InvalidAddress(format!("{}: invalid domain", domain)))
+  }
+}
+
+/// The address of a site, either a bitcoin-style address or a .bit domain
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(try_from = "String", into = "String")]
+pub struct SiteAddress(String);
+
+impl SiteAddress {
+  pub fn new<S: Into<String>>(address: S) -> Result<Self, Error> {
+    let address = address.into();
+    if address.ends_with(".bit") {
+      validate_domain(&address)?;
+    } else {
+      validate_address(&address)?;
+    }
+    Ok(SiteAddress(address))
+  }
+
+  pub fn is_domain(&self) -> bool {
+    self.0.ends_with(".bit")
+  }
+
+  pub fn as_str(&self) -> &str {
+    &self.0
+  }
+}
+
+/// The address a user signs their content with, the name of their data directory
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(try_from = "String", into = "String")]
+pub struct AuthAddress(String);
+
+impl AuthAddress {
+  pub fn new<S: Into<String>>(address: S) -> Result<Self, Error> {
+    let address = address.into();
+    validate_address(&address)?;
+    Ok(AuthAddress(address))
+  }
+
+  pub fn as_str(&self) -> &str {
+    &self.0
+  }
+}
+
+/// A user's certificate id, user_name@provider.bit
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct CertUserId {
+  user_name: String,
+  domain:    String,
+}
+
+impl CertUserId {
+  pub fn new<S1: Into<String>, S2: Into<String>>(user_name: S1, domain: S2) -> Result<Self, Error> {
+    let cert_user_id = CertUserId {
+      user_name: user_name.into(),
+      domain:    domain.into(),
+    };
+    if cert_user_id.user_name.is_empty()
+      || cert_user_id.user_name.contains('@')
+      || cert_user_id.domain.is_empty()
+    {
+      return Err(Error::InvalidCertUserId(cert_user_id.to_string()));
+    }
+    Ok(cert_user_id)
+  }
+
+  pub fn user_name(&self) -> &str {
+    &self.user_name
+  }
+
+  /// The domain of the certificate provider
+  pub fn domain(&self) -> &str {
+    &self.domain
+  }
+}
+
+impl FromStr for CertUserId {
+  type Err = Error;
+
+  fn from_str(cert_user_id: &str) -> Result<Self, Self::Err> {
+    match cert_user_id.split_once('@') {
+      Some((user_name, domain)) => CertUserId::new(user_name, domain),
+      None => Err(Error::InvalidCertUserId(cert_user_id.to_string())),
+    }
+  }
+}
+
+impl fmt::Display for CertUserId {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}@{}", self.user_name, self.domain)
+  }
+}
+
+impl TryFrom<String> for CertUserId {
+  type Error = Error;
+
+  fn try_from(cert_user_id: String) -> Result<Self, Self::Error> {
+    cert_user_id.parse()
+  }
+}
+
+impl From<CertUserId> for String {
+  fn from(cert_user_id: CertUserId) -> Self {
+    cert_user_id.to_string()
+  }
+}
+
+macro_rules! address_impls {
+  ($($ty:ident),*) => {
+    $(
+      impl fmt::Display for $ty {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+          f.write_str(&self.0)
+        }
+      }
+
+      impl AsRef<str> for $ty {
+        fn as_ref(&self) -> &str {
+          &self.0
+        }
+      }
+
+      impl FromStr for $ty {
+        type Err = Error;
+
+        fn from_str(address: &str) -> Result<Self, Self::Err> {
+          $ty::new(address)
+        }
+      }
+
+      impl TryFrom<String> for $ty {
+        type Error = Error;
+
+        fn try_from(address: String) -> Result<Self, Self::Error> {
+          $ty::new(address)
+        }
+      }
+
+      impl From<$ty> for String {
+        fn from(address: $ty) -> Self {
+          address.0
+        }
+      }
+    )*
+  };
+}
+
+address_impls!(SiteAddress, AuthAddress);
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const ADDRESS: &str = "1HeLLo4uzjaLetFx6NH3PMwFP3qbRbTf3D";
+
+  #[test]
+  fn site_address_accepts_addresses_and_domains() {
+    assert!(!SiteAddress::new(ADDRESS).unwrap().is_domain());
+    assert!(SiteAddress::new("zeroid.bit").unwrap().is_domain());
+    assert!(SiteAddress::new("sub.zeroid.bit").is_ok());
+  }
+
+  #[test]
+  fn site_address_rejects_invalid() {
+    assert!(SiteAddress::new("").is_err());
+    assert!(SiteAddress::new(".bit").is_err());
+    assert!(SiteAddress::new("-zeroid.bit").is_err());
+    assert!(SiteAddress::new("zero id.bit").is_err());
+    assert!(SiteAddress::new("zeroid.com").is_err());
+    // Changed last character, the checksum no longer matches
+    assert!(SiteAddress::new("1HeLLo4uzjaLetFx6NH3PMwFP3qbRbTf3E").is_err());
+    // 0 and l are not in the Base58 alphabet
+    assert!(SiteAddress::new("1HeLLo4uzjaLetFx6NH3PMwFP3qbRbTf30").is_err());
+  }
+
+  #[test]
+  fn auth_address_rejects_domains() {
+    assert!(AuthAddress::new(ADDRESS).is_ok());
+    assert!(AuthAddress::new("zeroid.bit").is_err());
+  }
+
+  #[test]
+  fn cert_user_id_parses_user_name_and_domain() {
+    let cert_user_id: CertUserId = "nofish@zeroid.bit".parse().unwrap();
+    assert_eq!(cert_user_id.user_name(), "nofish");
+    assert_eq!(cert_user_id.domain(), "zeroid.bit");
+    assert_eq!(cert_user_id.to_string(), "nofish@zeroid.bit");
+  }
+
+  #[test]
+  fn cert_user_id_rejects_invalid() {
+    assert!("nofish".parse::<CertUserId>().is_err());
+    assert!("@zeroid.bit".parse::<CertUserId>().is_err());
+    assert!("nofish@".parse::<CertUserId>().is_err());
+    assert!(CertUserId::new("a@b", "zeroid.bit").is_err());
+  }
+
+  #[test]
+  fn serde_round_trip() {
+    let address: AuthAddress = serde_json::from_str(&format!("\"{}\"", ADDRESS)).unwrap();
+    assert_eq!(
+      serde_json::to_string(&address).unwrap(),
+      format!("\"{}\"", ADDRESS)
+    );
+    let cert_user_id: CertUserId = serde_json::from_str("\"nofish@zeroid.bit\"").unwrap();
+    assert_eq!(
+      serde_json::to_string(&cert_user_id).unwrap(),
+      "\"nofish@zeroid.bit\""
+    );
+    assert!(serde_json::from_str::<AuthAddress>("\"invalid\"").is_err());
+  }
+}
@@ -1,4 +1,303 @@
-use super::{cmd, cmdp};
+use super::cmdp;
+use crate::content::{owning_content_json, ContentJson};
+use crate::inner_path::InnerPath;
+use crate::responses::{PortCheck, ServerConfig, SiteInfo, ZeroResponse};
+use crate::ui_server::{file_get_string, site_info, site_sign_with, SignOptions};
+use crate::ZeroFrameError as Error;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
 use wasm_bindgen::prelude::*;
 
-// TODO: admin functions
+/// Config keys that can be changed with config_set, ZeroNet's keys_api_change_allowed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigKey {
+  FileserverIpType,
+  FileserverPort,
+  IpExternal,
+  Language,
+  LogLevel,
+  Offline,
+  OpenBrowser,
+  ThreadsCrypt,
+  ThreadsDb,
+  ThreadsFsRead,
+  ThreadsFsWrite,
+  Tor,
+  TorUseBridges,
+  Trackers,
+  TrackersFile,
+  TrackersProxy,
+}
+
+impl fmt::Display for ConfigKey {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let key = match self {
+      ConfigKey::FileserverIpType => "fileserver_ip_type",
+      ConfigKey::FileserverPort => "fileserver_port",
+      ConfigKey::IpExternal => "ip_external",
+      ConfigKey::Language => "language",
+      ConfigKey::LogLevel => "log_level",
+      ConfigKey::Offline => "offline",
+      ConfigKey::OpenBrowser => "open_browser",
+      ConfigKey::ThreadsCrypt => "threads_crypt",
+      ConfigKey::ThreadsDb => "threads_db",
+      ConfigKey::ThreadsFsRead => "threads_fs_read",
+      ConfigKey::ThreadsFsWrite => "threads_fs_write",
+      ConfigKey::Tor => "tor",
+      ConfigKey::TorUseBridges => "tor_use_bridges",
+      ConfigKey::Trackers => "trackers",
+      ConfigKey::TrackersFile => "trackers_file",
+      ConfigKey::TrackersProxy => "trackers_proxy",
+    };
+    f.write_str(key)
+  }
+}
+
+/// Value for config_set, None resets the key to its default
+#[derive(Serialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum ConfigValue {
+  None,
+  Bool(bool),
+  Number(i64),
+  Text(String),
+  List(Vec<String>),
+}
+
+impl From<bool> for ConfigValue {
+  fn from(value: bool) -> Self {
+    ConfigValue::Bool(value)
+  }
+}
+
+impl From<i64> for ConfigValue {
+  fn from(value: i64) -> Self {
+    ConfigValue::Number(value)
+  }
+}
+
+impl From<u16> for ConfigValue {
+  fn from(value: u16) -> Self {
+    ConfigValue::Number(value as i64)
+  }
+}
+
+impl From<String> for ConfigValue {
+  fn from(value: String) -> Self {
+    ConfigValue::Text(value)
+  }
+}
+
+impl From<&str> for ConfigValue {
+  fn from(value: &str) -> Self {
+    ConfigValue::Text(value.to_string())
+  }
+}
+
+impl From<Vec<String>> for ConfigValue {
+  fn from(value: Vec<String>) -> Self {
+    ConfigValue::List(value)
+  }
+}
+
+#[derive(Deserialize)]
+struct SiteCloneResponse {
+  address: String,
+}
+
+#[derive(Deserialize)]
+struct ModifiedFilesResponse {
+  modified_files: Vec<InnerPath>,
+}
+
+/// List all sites, optionally including the sites that are still connecting
+pub async fn site_list(connecting_sites: bool) -> Result<Vec<SiteInfo>, Error> {
+  let response = cmdp("siteList", vec![JsValue::from_bool(connecting_sites)]).await;
+  response.response::<Vec<SiteInfo>>()
+}
+
+/// Delete a site and the user's data for it
+pub async fn site_delete(address: &str) -> Result<(), Error> {
+  let response = cmdp("siteDelete", vec![JsValue::from_str(address)]).await;
+  response.result_message("Deleted")
+}
+
+/// Stop serving a site
+pub async fn site_pause(address: &str) -> Result<(), Error> {
+  let response = cmdp("sitePause", vec![JsValue::from_str(address)]).await;
+  response.result_message("Paused")
+}
+
+/// Resume serving a paused site
+pub async fn site_resume(address: &str) -> Result<(), Error> {
+  let response = cmdp("siteResume", vec![JsValue::from_str(address)]).await;
+  response.result_message("Resumed")
+}
+
+/// Clone a site, returns the address of the new site
+/// Without a target address a new site is created from the site's clone_root
+pub async fn site_clone(
+  address: &str,
+  root_inner_path: Option<InnerPath>,
+  target_address: Option<String>,
+  redirect: bool,
+) -> Result<String, Error> {
+  let response = cmdp(
+    "siteClone",
+    vec![
+      JsValue::from_str(address),
+      JsValue::from_str(root_inner_path.unwrap_or_default().as_str()),
+      target_address
+        .map(|address| JsValue::from_str(&address))
+        .unwrap_or(JsValue::null()),
+      JsValue::from_bool(redirect),
+    ],
+  )
+  .await;
+  Ok(response.response::<SiteCloneResponse>()?.address)
+}
+
+/// Set the size limit of the current site in MB
+pub async fn site_set_limit(size_limit: usize) -> Result<(), Error> {
+  let response = cmdp("siteSetLimit", vec![JsValue::from_f64(size_limit as f64)]).await;
+  response.result()
+}
+
+/// Set a value in the current site's settings
+/// ZeroNet only allows modified_files_notification to be set this way
+pub async fn site_set_settings_value<T: Serialize>(key: &str, value: T) -> Result<(), Error> {
+  let response = cmdp(
+    "siteSetSettingsValue",
+    vec![JsValue::from_str(key), JsValue::from_serde(&value)?],
+  )
+  .await;
+  response.result()
+}
+
+/// List files that were modified since the content.json was last signed
+pub async fn site_list_modified_files(
+  content_inner_path: Option<InnerPath>,
+) -> Result<Vec<InnerPath>, Error> {
+  let content_inner_path = content_inner_path.unwrap_or_else(|| InnerPath::root().content_json());
+  let response = cmdp(
+    "siteListModifiedFiles",
+    vec![JsValue::from_str(content_inner_path.as_str())],
+  )
+  .await;
+  Ok(response.response::<ModifiedFilesResponse>()?.modified_files)
+}
+
+/// Sign every content.json that has modified files
+/// Lists the modified files of the root content.json, its includes and the current user's
+/// content.json, and signs the content.json each file belongs to.
+/// Returns the signed content.json files.
+pub async fn site_sign_modified(options: &SignOptions) -> Result<Vec<InnerPath>, Error> {
+  let auth_address = site_info().await?.auth_address;
+  let mut pending = vec![InnerPath::root().content_json()];
+  let mut scanned = BTreeSet::new();
+  let mut content_jsons = BTreeSet::new();
+  while let Some(content_inner_path) = pending.pop() {
+    if !scanned.insert(content_inner_path.clone()) {
+      continue;
+    }
+    let content: ContentJson = match file_get_string(&content_inner_path, false, None).await? {
+      Some(content) => content.parse()?,
+      None => continue,
+    };
+    let directory = content_inner_path.parent().unwrap_or_default();
+    for include in content.includes.keys() {
+      pending.push(directory.join(include)?);
+    }
+    if let (Some(_), Some(auth_address)) = (&content.user_contents, &auth_address) {
+      pending.push(
+        directory
+          .join(auth_address.as_str())?
+          .join("content.json")?,
+      );
+    }
+    for inner_path in site_list_modified_files(Some(content_inner_path)).await? {
+      content_jsons.insert(owning_content_json(&inner_path).await?);
+    }
+  }
+
+  for content_json in &content_jsons {
+    site_sign_with(content_json, options).await?;
+  }
+  Ok(content_jsons.into_iter().collect())
+}
+
+/// Ask the user to confirm updating ZeroNet to the latest version and restarting
+/// Returns Ok(false) if the user declined
+pub async fn server_update() -> Result<bool, Error> {
+  let response = cmdp("serverUpdate", vec![]).await;
+  confirmed(response)
+}
+
+/// Ask the user to confirm shutting down or restarting ZeroNet
+/// Returns Ok(false) if the user declined
+pub async fn server_shutdown(restart: bool) -> Result<bool, Error> {
+  let response = cmdp("serverShutdown", vec![JsValue::from_bool(restart)]).await;
+  confirmed(response)
+}
+
+/// ZeroNet replies with the result of its confirm dialog, the index of the button or a bool
+fn confirmed(response: JsValue) -> Result<bool, Error> {
+  if let Some(confirmed) = response.as_bool() {
+    return Ok(confirmed);
+  }
+  if let Some(button) = response.as_f64() {
+    return Ok(button != 0.0);
+  }
+  if response.is_null() || response.is_undefined() {
+    return Ok(false);
+  }
+  response.result().map(|_| true)
+}
+
+/// Check whether the fileserver port is reachable from the outside
+pub async fn server_portcheck() -> Result<PortCheck, Error> {
+  let response = cmdp("serverPortcheck", vec![]).await;
+  response.response::<PortCheck>()
+}
+
+/// Open a directory of the ZeroNet data directory in the file browser
+pub async fn server_showdirectory(
+  directory: &str,
+  inner_path: Option<String>,
+) -> Result<(), Error> {
+  let response = cmdp(
+    "serverShowdirectory",
+    vec![
+      JsValue::from_str(directory),
+      JsValue::from_str(&inner_path.unwrap_or_default()),
+    ],
+  )
+  .await;
+  response.result()
+}
+
+/// Get a nonce that allows loading the wrapper once without the referer check
+pub async fn server_get_wrapper_nonce() -> Result<String, Error> {
+  let response = cmdp("serverGetWrapperNonce", vec![]).await;
+  response.response::<String>()
+}
+
+/// Change a config value of the ZeroNet client
+pub async fn config_set<V: Into<ConfigValue>>(key: ConfigKey, value: V) -> Result<(), Error> {
+  let response = cmdp(
+    "configSet",
+    vec![
+      JsValue::from_str(&key.to_string()),
+      JsValue::from_serde(&value.into())?,
+    ],
+  )
+  .await;
+  response.result()
+}
+
+/// List the config values of the ZeroNet client, requires the UiConfig plugin
+pub async fn config_list() -> Result<ServerConfig, Error> {
+  let response = cmdp("configList", vec![]).await;
+  response.response::<ServerConfig>()
+}
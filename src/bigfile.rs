@@ -1,12 +1,13 @@
 use super::cmdp;
+use crate::inner_path::InnerPath;
 use wasm_bindgen::prelude::*;
 
 // TODO: rustify big file upload
-pub async fn bigfile_upload_init(inner_path: &str, size: usize) -> JsValue {
+pub async fn bigfile_upload_init(inner_path: &InnerPath, size: usize) -> JsValue {
   cmdp(
     "bigfileUploadInit",
     vec![
-      JsValue::from_str(inner_path),
+      JsValue::from_str(inner_path.as_str()),
       JsValue::from_f64(size as f64),
     ],
   )
@@ -1,19 +1,21 @@
 use super::cmdp;
+use crate::query::QueryParams;
 use crate::responses::{PeerLocation, ZeroResponse};
 use crate::ZeroFrameError as Error;
 use serde::de::DeserializeOwned;
-use std::collections::HashMap;
 use wasm_bindgen::prelude::*;
 
+/// Run a query on the stats database of the Chart plugin
+/// Params can be a QueryParams or a HashMap<String, String>
 pub async fn db_query<T: DeserializeOwned>(
   query: &str,
-  params: HashMap<String, String>,
+  params: impl Into<QueryParams>,
 ) -> Result<Vec<T>, Error> {
   let response = cmdp(
     "chartDbQuery",
     vec![
       JsValue::from_str(query),
-      JsValue::from_serde(&params).unwrap(),
+      JsValue::from_serde(&params.into())?,
     ],
   )
   .await;
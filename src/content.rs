@@ -0,0 +1,393 @@
+use super::new_regexp;
+use crate::address::AuthAddress;
+use crate::inner_path::{InnerPath, IntoInnerPath};
+use crate::ui_server::{file_get_string, file_rules};
+use crate::ZeroFrameError as Error;
+use js_sys::RegExp;
+use serde::{Deserialize, Serialize};
+use serde_json::ser::Formatter;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::io;
+use std::str::FromStr;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FileEntry {
+  pub sha512: String,
+  pub size:   u64,
+}
+
+/// A content.json included from the parent's `includes`
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Include {
+  pub signers:          Vec<String>,
+  pub signers_required: u64,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub files_allowed:    Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub includes_allowed: Option<bool>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub max_size:         Option<u64>,
+}
+
+/// Rules for users' content, from user_contents.permission_rules or user_contents.permissions
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct PermissionRules {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub files_allowed:          Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub files_allowed_optional: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub max_size:               Option<u64>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub max_size_optional:      Option<u64>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub signers:                Option<Vec<String>>,
+}
+
+/// Per user permissions, a user can be banned by setting false
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum UserPermission {
+  Banned(bool),
+  Rules(PermissionRules),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct UserContents {
+  pub cert_signers:         HashMap<String, Vec<String>>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub cert_signers_pattern: Option<String>,
+  pub permission_rules:     HashMap<String, PermissionRules>,
+  pub permissions:          HashMap<String, UserPermission>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub optional:             Option<String>,
+}
+
+/// A content.json file of a site, an include or a user directory
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ContentJson {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub address:        Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub inner_path:     Option<String>,
+  pub files:          HashMap<String, FileEntry>,
+  #[serde(skip_serializing_if = "HashMap::is_empty")]
+  pub files_optional: HashMap<String, FileEntry>,
+  #[serde(skip_serializing_if = "HashMap::is_empty")]
+  pub includes:       HashMap<String, Include>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub user_contents:  Option<UserContents>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub signers:        Vec<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub signers_sign:   Option<String>,
+  #[serde(skip_serializing_if = "HashMap::is_empty")]
+  pub signs:          HashMap<String, String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub signs_required: Option<u64>,
+  pub modified:       f64,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub optional:       Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub ignore:         Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub cert_auth_type: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub cert_user_id:   Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub cert_sign:      Option<String>,
+  /// Site specific keys like title, description or merged_type
+  #[serde(flatten)]
+  pub other:          Map<String, Value>,
+}
+
+impl FromStr for ContentJson {
+  type Err = Error;
+
+  fn from_str(content: &str) -> Result<Self, Self::Err> {
+    Ok(serde_json::from_str(content)?)
+  }
+}
+
+impl ContentJson {
+  /// The size ZeroNet checks against max_size: the content.json itself and its non-optional files
+  /// The content.json is measured as Python's json.dumps would serialize it.
+  pub fn size(&self) -> Result<u64, Error> {
+    let own_size = to_python_json(self)?.len() as u64;
+    Ok(own_size + self.files.values().map(|file| file.size).sum::<u64>())
+  }
+
+  /// The size after replacing or adding a non-optional file
+  pub fn size_with_file(&self, relative_path: &str, size: u64) -> Result<u64, Error> {
+    let old_size = self
+      .files
+      .get(relative_path)
+      .map(|file| file.size)
+      .unwrap_or_default();
+    Ok(self.size()? - old_size + size)
+  }
+
+  pub fn is_optional(&self, relative_path: &str) -> Result<bool, Error> {
+    match &self.optional {
+      Some(pattern) => full_match(pattern, relative_path),
+      None => Ok(false),
+    }
+  }
+
+  pub fn is_ignored(&self, relative_path: &str) -> Result<bool, Error> {
+    match &self.ignore {
+      Some(pattern) => full_match(pattern, relative_path),
+      None => Ok(false),
+    }
+  }
+
+  /// Check a file, relative to this content.json, against the rules before writing it
+  pub fn validate_write(
+    &self,
+    relative_path: &str,
+    size: u64,
+    files_allowed: &str,
+    max_size: Option<u64>,
+  ) -> Result<(), Error> {
+    validate_path(relative_path, files_allowed)?;
+    if self.is_optional(relative_path)? {
+      return Ok(());
+    }
+    if let Some(max_size) = max_size {
+      let size = self.size_with_file(relative_path, size)?;
+      if size > max_size {
+        return Err(Error::SizeLimitExceeded { size, max_size });
+      }
+    }
+    Ok(())
+  }
+
+  /// The content.json that signs a file below the directory of this content.json
+  pub fn owner_of(
+    &self,
+    directory: &InnerPath,
+    inner_path: &InnerPath,
+  ) -> Result<InnerPath, Error> {
+    let relative_path = inner_path.strip_prefix(directory).unwrap_or_default();
+    let include = self
+      .includes
+      .keys()
+      .find(|include| match include.strip_suffix("content.json") {
+        Some(include_directory) => {
+          !include_directory.is_empty() && relative_path.starts_with(include_directory)
+        }
+        None => false,
+      });
+    if let Some(include) = include {
+      return directory.join(include);
+    }
+    match relative_path.split_once('/') {
+      Some((user_directory, _)) if self.user_contents.is_some() => {
+        directory.join(user_directory)?.join("content.json")
+      }
+      _ => directory.join("content.json"),
+    }
+  }
+}
+
+/// Check a path relative to its content.json against a files_allowed pattern
+pub fn validate_path(relative_path: &str, files_allowed: &str) -> Result<(), Error> {
+  if full_match(files_allowed, relative_path)? {
+    Ok(())
+  } else {
+    Err(Error::FileNotAllowed {
+      inner_path:    relative_path.to_string(),
+      files_allowed: files_allowed.to_string(),
+    })
+  }
+}
+
+/// Serialize like Python's json.dumps: ", " and ": " separators and non-ASCII escaped
+fn to_python_json<T: Serialize>(value: &T) -> Result<String, Error> {
+  let mut json = Vec::new();
+  let mut serializer = serde_json::Serializer::with_formatter(&mut json, PythonFormatter);
+  value.serialize(&mut serializer)?;
+  String::from_utf8(json).map_err(|_| Error::InvalidResponse)
+}
+
+struct PythonFormatter;
+
+impl Formatter for PythonFormatter {
+  fn begin_array_value<W: ?Sized + io::Write>(
+    &mut self,
+    writer: &mut W,
+    first: bool,
+  ) -> io::Result<()> {
+    if first {
+      Ok(())
+    } else {
+      writer.write_all(b", ")
+    }
+  }
+
+  fn begin_object_key<W: ?Sized + io::Write>(
+    &mut self,
+    writer: &mut W,
+    first: bool,
+  ) -> io::Result<()> {
+    if first {
+      Ok(())
+    } else {
+      writer.write_all(b", ")
+    }
+  }
+
+  fn begin_object_value<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+    writer.write_all(b": ")
+  }
+
+  fn write_string_fragment<W: ?Sized + io::Write>(
+    &mut self,
+    writer: &mut W,
+    fragment: &str,
+  ) -> io::Result<()> {
+    for c in fragment.chars() {
+      if c.is_ascii() {
+        writer.write_all(&[c as u8])?;
+      } else {
+        let mut utf16 = [0; 2];
+        for unit in c.encode_utf16(&mut utf16) {
+          write!(writer, "\\u{:04x}", unit)?;
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+/// ZeroNet patterns have to match the whole path
+pub(crate) fn full_match(pattern: &str, text: &str) -> Result<bool, Error> {
+  Ok(regexp(&format!("^(?:{})$", pattern))?.test(text))
+}
+
+/// Compile a pattern, JavaScript rejects some Python regex syntax ZeroNet accepts
+pub(crate) fn regexp(pattern: &str) -> Result<RegExp, Error> {
+  new_regexp(pattern, "").map_err(|_| Error::InvalidPattern(pattern.to_string()))
+}
+
+/// Read and parse a content.json
+pub async fn content_json_get<P: IntoInnerPath>(inner_path: P) -> Result<ContentJson, Error> {
+  let content = file_get_string(inner_path, true, None)
+    .await?
+    .ok_or(Error::FalsyResponse)?;
+  content.parse()
+}
+
+/// Read and parse the content.json of a user's data directory
+pub async fn user_content_json_get(auth_address: &AuthAddress) -> Result<ContentJson, Error> {
+  content_json_get(InnerPath::user_directory(auth_address)?.join("content.json")?).await
+}
+
+/// Find the content.json that signs a file, walking up from its directory like ZeroNet's getFileInfo
+/// Files below a directory with user_contents or includes belong to the user's or include's
+/// content.json, even before it is signed for the first time.
+pub async fn owning_content_json(inner_path: &InnerPath) -> Result<InnerPath, Error> {
+  if inner_path.file_name() == Some("content.json") {
+    return Ok(inner_path.clone());
+  }
+  let mut directory = inner_path.parent();
+  while let Some(current) = directory {
+    if let Some(content) = file_get_string(current.join("content.json")?, false, None).await? {
+      let content: ContentJson = content.parse()?;
+      return content.owner_of(&current, inner_path);
+    }
+    directory = current.parent();
+  }
+  Ok(InnerPath::root().content_json())
+}
+
+/// Check a write against the fileRules of the content.json that signs the file,
+/// so it is rejected before the content.json fails to sign.
+/// Optional files are checked against files_allowed_optional and do not count against max_size.
+pub async fn file_rules_check(inner_path: &InnerPath, size: u64) -> Result<(), Error> {
+  let content_inner_path = owning_content_json(inner_path).await?;
+  let directory = content_inner_path.parent().unwrap_or_default();
+  let relative_path = inner_path.strip_prefix(&directory).unwrap_or_default();
+  let rules = file_rules(&content_inner_path).await?;
+  let content: ContentJson = match file_get_string(&content_inner_path, false, None).await? {
+    Some(content) => content.parse()?,
+    None => ContentJson::default(),
+  };
+
+  if content.is_optional(relative_path)? {
+    if let Some(files_allowed_optional) = &rules.files_allowed_optional {
+      validate_path(relative_path, files_allowed_optional)?;
+    }
+    return Ok(());
+  }
+  if !rules.files_allowed.is_empty() {
+    validate_path(relative_path, &rules.files_allowed)?;
+  }
+  if rules.max_size == 0 {
+    return Ok(());
+  }
+  let old_size = content
+    .files
+    .get(relative_path)
+    .map(|file| file.size)
+    .unwrap_or_default();
+  let new_size = (rules.current_size as u64).saturating_sub(old_size) + size;
+  if new_size > rules.max_size as u64 {
+    return Err(Error::SizeLimitExceeded {
+      size:     new_size,
+      max_size: rules.max_size as u64,
+    });
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn python_json() {
+    let value =
+      serde_json::json!({"files": {"a.json": {"size": 1}}, "list": [1, 2], "title": "Café 🌐"});
+    assert_eq!(
+      to_python_json(&value).unwrap(),
+      r#"{"files": {"a.json": {"size": 1}}, "list": [1, 2], "title": "Caf\u00e9 \ud83c\udf10"}"#
+    );
+  }
+
+  fn path(inner_path: &str) -> InnerPath {
+    InnerPath::new(inner_path).unwrap()
+  }
+
+  #[test]
+  fn owner_of_files_in_the_directory() {
+    let content = ContentJson::default();
+    let owner = content.owner_of(&InnerPath::root(), &path("img/logo.png"));
+    assert_eq!(owner.unwrap(), path("content.json"));
+    let owner = content.owner_of(&path("data/users/1A"), &path("data/users/1A/img/a.png"));
+    assert_eq!(owner.unwrap(), path("data/users/1A/content.json"));
+  }
+
+  #[test]
+  fn owner_of_user_files() {
+    let content: ContentJson = r#"{"user_contents": {"permissions": {}}}"#.parse().unwrap();
+    let owner = content.owner_of(&path("data/users"), &path("data/users/1A/img/a.png"));
+    assert_eq!(owner.unwrap(), path("data/users/1A/content.json"));
+  }
+
+  #[test]
+  fn owner_of_included_files() {
+    let content: ContentJson = r#"{"includes": {"data/archive/content.json": {}}}"#
+      .parse()
+      .unwrap();
+    let owner = content.owner_of(&InnerPath::root(), &path("data/archive/2020/post.json"));
+    assert_eq!(owner.unwrap(), path("data/archive/content.json"));
+    let owner = content.owner_of(&InnerPath::root(), &path("data/archived.json"));
+    assert_eq!(owner.unwrap(), path("content.json"));
+  }
+}
@@ -1,71 +1,129 @@
 use super::cmdp;
+use crate::responses::{AesEncrypted, Base64, EciesEncrypted, PublicKey, ZeroResponse};
+use crate::ZeroFrameError as Error;
 use wasm_bindgen::prelude::*;
 
-pub async fn user_publickey(index: Option<usize>) -> JsValue {
+/// Get the user's public key for this site, optionally using a different key index
+pub async fn user_publickey(index: Option<usize>) -> Result<PublicKey, Error> {
   let params = match index {
     Some(index) => vec![JsValue::from_f64(index as f64)],
     None => vec![],
   };
-  cmdp("userPublickey", params).await
+  let response = cmdp("userPublickey", params).await;
+  response.response::<PublicKey>()
 }
 
-pub async fn ecies_encrypt(text: &str, publickey_index: usize, return_aes_key: bool) -> JsValue {
-  cmdp(
+/// Encrypt a text for the user's own public key at the given index
+pub async fn ecies_encrypt(
+  text: &str,
+  publickey_index: usize,
+  return_aes_key: bool,
+) -> Result<EciesEncrypted, Error> {
+  ecies_encrypt_with(
+    text,
+    JsValue::from_f64(publickey_index as f64),
+    return_aes_key,
+  )
+  .await
+}
+
+/// Encrypt a text for an explicit recipient public key
+pub async fn ecies_encrypt_to(
+  text: &str,
+  publickey: &PublicKey,
+  return_aes_key: bool,
+) -> Result<EciesEncrypted, Error> {
+  ecies_encrypt_with(text, JsValue::from_str(publickey.as_str()), return_aes_key).await
+}
+
+async fn ecies_encrypt_with(
+  text: &str,
+  publickey: JsValue,
+  return_aes_key: bool,
+) -> Result<EciesEncrypted, Error> {
+  let response = cmdp(
     "eciesEncrypt",
     vec![
       JsValue::from_str(text),
-      JsValue::from_f64(publickey_index as f64),
+      publickey,
       JsValue::from_bool(return_aes_key),
     ],
   )
-  .await
+  .await;
+  response.response::<EciesEncrypted>()
 }
 
-pub async fn ecies_decrypt(params: &str, privatekey_index: usize) -> JsValue {
-  cmdp(
+/// Decrypt a text with the user's private key at the given index
+/// Returns Err(FalsyResponse) if the text could not be decrypted
+pub async fn ecies_decrypt(ciphertext: &Base64, privatekey_index: usize) -> Result<String, Error> {
+  let response = cmdp(
     "eciesDecrypt",
     vec![
-      JsValue::from_str(params),
+      JsValue::from_str(ciphertext.as_str()),
       JsValue::from_f64(privatekey_index as f64),
     ],
   )
-  .await
-}
-
-pub async fn ecies_decrypt_multiple() {
-  unimplemented!()
+  .await;
+  response
+    .response::<Option<String>>()?
+    .ok_or(Error::FalsyResponse)
 }
 
-pub async fn aes_encrypt(text: &str, key: Option<String>, iv: Option<String>) -> JsValue {
-  cmdp(
-    "aesEncrypt",
+/// Try to decrypt multiple texts at once
+/// Texts that could not be decrypted with the private key are returned as None
+pub async fn ecies_decrypt_multiple(
+  ciphertexts: &[Base64],
+  privatekey_index: usize,
+) -> Result<Vec<Option<String>>, Error> {
+  let response = cmdp(
+    "eciesDecrypt",
     vec![
-      JsValue::from_str(text),
-      JsValue::from_str(&key.unwrap_or("generate new".to_string())),
-      JsValue::from_str(&iv.unwrap_or("generate new".to_string())),
+      JsValue::from_serde(ciphertexts)?,
+      JsValue::from_f64(privatekey_index as f64),
     ],
   )
-  .await
+  .await;
+  response.response::<Vec<Option<String>>>()
 }
 
-pub async fn aes_decrypt(iv: &str, encrypted_text: &str, key: &str) -> JsValue {
-  cmdp(
+/// Encrypt a text with AES, a new key is generated if none is given
+/// ZeroNet always generates a new iv, it is returned with the ciphertext.
+pub async fn aes_encrypt(text: &str, key: Option<&Base64>) -> Result<AesEncrypted, Error> {
+  let mut params = vec![JsValue::from_str(text)];
+  if let Some(key) = key {
+    params.push(JsValue::from_str(key.as_str()));
+  }
+  let response = cmdp("aesEncrypt", params).await;
+  response.response::<AesEncrypted>()
+}
+
+/// Decrypt an AES encrypted text
+/// Returns Err(FalsyResponse) if the text could not be decrypted with the key
+pub async fn aes_decrypt(iv: &Base64, ciphertext: &Base64, key: &Base64) -> Result<String, Error> {
+  let response = cmdp(
     "aesDecrypt",
     vec![
-      JsValue::from_str(iv),
-      JsValue::from_str(encrypted_text),
-      JsValue::null(),
-      JsValue::from_str(key),
+      JsValue::from_str(iv.as_str()),
+      JsValue::from_str(ciphertext.as_str()),
+      JsValue::from_str(key.as_str()),
     ],
   )
-  .await
+  .await;
+  response
+    .response::<Option<String>>()?
+    .ok_or(Error::FalsyResponse)
 }
 
-pub async fn aes_decrypt_multiple() {
-  unimplemented!()
-  // TODO:
-  // cmdp("aesDecrypt", vec![
-  //   JsValue::from_str(iv),
-  //   JsValue::null(),
-  // ]).await
+/// Try to decrypt multiple (iv, ciphertext) pairs with each of the keys
+/// Texts that could not be decrypted with any of the keys are returned as None
+pub async fn aes_decrypt_multiple(
+  encrypted: &[(Base64, Base64)],
+  keys: &[Base64],
+) -> Result<Vec<Option<String>>, Error> {
+  let response = cmdp(
+    "aesDecrypt",
+    vec![JsValue::from_serde(encrypted)?, JsValue::from_serde(keys)?],
+  )
+  .await;
+  response.response::<Vec<Option<String>>>()
 }
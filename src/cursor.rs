@@ -0,0 +1,173 @@
+use crate::query::{escape_identifier, Query, QueryParams};
+use crate::ui_server::db_query;
+use crate::ZeroFrameError as Error;
+use futures::stream::{self, Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+
+/// How a Cursor fetches the next page
+#[derive(Clone, Debug)]
+pub enum Pagination {
+  /// LIMIT/OFFSET on the query as it is
+  Offset,
+  /// Continue after the last value of a column of the result, which has to be unique
+  Keyset {
+    column:     String,
+    descending: bool,
+  },
+}
+
+/// Pages through the results of a database query
+///
+/// ```ignore
+/// let mut topics = Cursor::new("SELECT * FROM topic", QueryParams::new())
+///   .keyset("topic_id", true)
+///   .page_size(25)
+///   .stream::<Topic>();
+/// while let Some(topic) = topics.next().await {
+///   render(topic?);
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Cursor {
+  sql:        String,
+  params:     QueryParams,
+  page_size:  usize,
+  pagination: Pagination,
+}
+
+struct CursorState {
+  cursor:   Cursor,
+  offset:   usize,
+  last_key: Option<Value>,
+}
+
+impl Cursor {
+  pub fn new<S: Into<String>, P: Into<QueryParams>>(sql: S, params: P) -> Self {
+    Cursor {
+      sql:        sql.into(),
+      params:     params.into(),
+      page_size:  DEFAULT_PAGE_SIZE,
+      pagination: Pagination::Offset,
+    }
+  }
+
+  pub fn from_query(query: Query) -> Self {
+    let (sql, params) = query.build();
+    Cursor::new(sql, params)
+  }
+
+  pub fn page_size(mut self, page_size: usize) -> Self {
+    self.page_size = page_size.max(1);
+    self
+  }
+
+  /// Use keyset pagination on a column as it is named in the result, like `topic_id`
+  /// rather than `topic.topic_id`. Pages fail if the rows do not contain it.
+  pub fn keyset<S: Into<String>>(mut self, column: S, descending: bool) -> Self {
+    self.pagination = Pagination::Keyset {
+      column: column.into(),
+      descending,
+    };
+    self
+  }
+
+  fn page_query(
+    &self,
+    offset: usize,
+    last_key: &Option<Value>,
+  ) -> Result<(String, QueryParams), Error> {
+    let mut params = self.params.clone();
+    let sql = match &self.pagination {
+      Pagination::Offset => format!(
+        "SELECT * FROM ({}) LIMIT {} OFFSET {}",
+        self.sql, self.page_size, offset
+      ),
+      Pagination::Keyset { column, descending } => {
+        let (op, direction) = if *descending {
+          ("<", "DESC")
+        } else {
+          (">", "ASC")
+        };
+        let condition = match last_key {
+          Some(last_key) => {
+            params.insert("cursor_last_key", last_key)?;
+            format!(
+              " WHERE {} {} :cursor_last_key",
+              escape_identifier(column),
+              op
+            )
+          }
+          None => String::new(),
+        };
+        format!(
+          "SELECT * FROM ({}){} ORDER BY {} {} LIMIT {}",
+          self.sql,
+          condition,
+          escape_identifier(column),
+          direction,
+          self.page_size
+        )
+      }
+    };
+    Ok((sql, params))
+  }
+
+  /// Stream the results page by page, the stream ends after the first short page
+  pub fn pages<T: DeserializeOwned>(self) -> impl Stream<Item = Result<Vec<T>, Error>> {
+    let state = CursorState {
+      cursor:   self,
+      offset:   0,
+      last_key: None,
+    };
+    stream::unfold(Some(state), |state| async move {
+      let mut state = state?;
+      let rows = match state.next_page().await {
+        Ok(rows) => rows,
+        Err(err) => return Some((Err(err), None)),
+      };
+      if rows.is_empty() {
+        return None;
+      }
+      let done = rows.len() < state.cursor.page_size;
+      let page = rows
+        .into_iter()
+        .map(|row| serde_json::from_value(row).map_err(Error::from))
+        .collect();
+      Some((page, if done { None } else { Some(state) }))
+    })
+  }
+
+  /// Stream the results row by row, fetching a new page when needed
+  pub fn stream<T: DeserializeOwned>(self) -> impl Stream<Item = Result<T, Error>> {
+    self.pages::<T>().flat_map(|page| {
+      let rows: Vec<Result<T, Error>> = match page {
+        Ok(rows) => rows.into_iter().map(Ok).collect(),
+        Err(err) => vec![Err(err)],
+      };
+      stream::iter(rows)
+    })
+  }
+}
+
+impl CursorState {
+  async fn next_page(&mut self) -> Result<Vec<Value>, Error> {
+    let (sql, params) = self.cursor.page_query(self.offset, &self.last_key)?;
+    let rows = db_query::<Value>(sql, params).await?;
+    self.offset += rows.len();
+    if let Pagination::Keyset { column, .. } = &self.cursor.pagination {
+      self.last_key = rows
+        .last()
+        .and_then(|row| row.get(column))
+        .filter(|key| !key.is_null())
+        .cloned();
+      // Without the key the next page would start over at the first one
+      if self.last_key.is_none() && rows.len() >= self.cursor.page_size {
+        return Err(Error::MissingKeysetColumn(column.clone()));
+      }
+    }
+    Ok(rows)
+  }
+}
@@ -0,0 +1,376 @@
+use crate::ui_server::file_get_string;
+use crate::ZeroFrameError as Error;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const JSON_ID_COLUMN: &str = "json_id";
+pub const JSON_ID_TYPE: &str = "INTEGER REFERENCES json (json_id)";
+
+/// A site's dbschema.json
+///
+/// ```ignore
+/// let schema = DbSchema::new("ZeroTalk", "data/zerotalk.db")
+///   .map("users/.+/data.json", FileMap::new().to_table("topic"))
+///   .map("users/.+/content.json", FileMap::new().to_json_table("cert_user_id"))
+///   .table_of::<Topic>();
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DbSchema {
+  pub db_name: String,
+  pub db_file: String,
+  #[serde(default = "default_version")]
+  pub version: u8,
+  #[serde(default)]
+  pub maps:    BTreeMap<String, FileMap>,
+  #[serde(default)]
+  pub tables:  BTreeMap<String, Table>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub feeds:   Option<BTreeMap<String, String>>,
+}
+
+fn default_version() -> u8 {
+  1
+}
+
+/// How the content of json files matching a map pattern is imported
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct FileMap {
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub to_table:      Vec<ToTable>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub to_keyvalue:   Vec<String>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub to_json_table: Vec<String>,
+}
+
+/// A node imported into a table, either into the table of the same name or with options
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum ToTable {
+  Node(String),
+  Table {
+    node:        String,
+    table:       String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key_col:     Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    val_col:     Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    import_cols: Option<Vec<String>>,
+  },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Table {
+  pub cols:           Vec<(String, String)>,
+  pub indexes:        Vec<String>,
+  pub schema_changed: u64,
+}
+
+/// SQLite column type of a Rust type
+pub trait SqlType {
+  const SQL_TYPE: &'static str;
+}
+
+macro_rules! sql_type {
+  ($sql_type:literal, $($ty:ty),*) => {
+    $(impl SqlType for $ty {
+      const SQL_TYPE: &'static str = $sql_type;
+    })*
+  };
+}
+
+sql_type!("INTEGER", bool, i8, i16, i32, i64, u8, u16, u32, u64, usize, isize);
+sql_type!("REAL", f32, f64);
+sql_type!("TEXT", String, &str);
+
+impl<T: SqlType> SqlType for Option<T> {
+  const SQL_TYPE: &'static str = T::SQL_TYPE;
+}
+
+/// A row type with its table definition, usually implemented with db_table!
+pub trait DbTable {
+  const TABLE: &'static str;
+  fn table() -> Table;
+}
+
+/// Declare a row struct together with its DbTable implementation,
+/// so the table in dbschema.json matches the type passed to db_query.
+/// A json_id column is added unless the struct declares one.
+///
+/// ```ignore
+/// db_table! {
+///   table = "topic", schema_changed = 1;
+///   #[derive(Serialize, Deserialize)]
+///   pub struct Topic {
+///     pub topic_id: i64,
+///     pub title: String,
+///     pub body: Option<String>,
+///   }
+/// }
+/// ```
+#[macro_export]
+macro_rules! db_table {
+  (
+    table = $table:literal, schema_changed = $schema_changed:literal;
+    $(#[$meta:meta])*
+    $vis:vis struct $name:ident {
+      $($(#[$field_meta:meta])* $field_vis:vis $field:ident : $ty:ty),* $(,)?
+    }
+  ) => {
+    $(#[$meta])*
+    $vis struct $name {
+      $($(#[$field_meta])* $field_vis $field: $ty),*
+    }
+
+    impl $crate::dbschema::DbTable for $name {
+      const TABLE: &'static str = $table;
+
+      fn table() -> $crate::dbschema::Table {
+        $crate::dbschema::Table::new($schema_changed)
+          $(.col(stringify!($field), <$ty as $crate::dbschema::SqlType>::SQL_TYPE))*
+          .with_json_id()
+      }
+    }
+  };
+}
+
+impl DbSchema {
+  pub fn new<S1: Into<String>, S2: Into<String>>(db_name: S1, db_file: S2) -> Self {
+    DbSchema {
+      db_name: db_name.into(),
+      db_file: db_file.into(),
+      version: 2,
+      maps:    BTreeMap::new(),
+      tables:  BTreeMap::new(),
+      feeds:   None,
+    }
+  }
+
+  pub fn version(mut self, version: u8) -> Self {
+    self.version = version;
+    self
+  }
+
+  /// Map json files matching the pattern, relative to the directory of db_file
+  pub fn map<S: Into<String>>(mut self, pattern: S, map: FileMap) -> Self {
+    self.maps.insert(pattern.into(), map);
+    self
+  }
+
+  pub fn table<S: Into<String>>(mut self, name: S, table: Table) -> Self {
+    self.tables.insert(name.into(), table);
+    self
+  }
+
+  /// Add the table of a row type declared with db_table!
+  pub fn table_of<T: DbTable>(self) -> Self {
+    self.table(T::TABLE, T::table())
+  }
+
+  pub fn feed<S1: Into<String>, S2: Into<String>>(mut self, name: S1, query: S2) -> Self {
+    self
+      .feeds
+      .get_or_insert_with(BTreeMap::new)
+      .insert(name.into(), query.into());
+    self
+  }
+
+  /// The schema as it should be written to dbschema.json
+  pub fn to_json(&self) -> Result<String, Error> {
+    Ok(serde_json::to_string_pretty(self)?)
+  }
+}
+
+impl FileMap {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Import the node into the table of the same name
+  pub fn to_table<S: Into<String>>(mut self, node: S) -> Self {
+    self.to_table.push(ToTable::Node(node.into()));
+    self
+  }
+
+  /// Import the node into a table with a different name
+  pub fn to_named_table<S1: Into<String>, S2: Into<String>>(mut self, node: S1, table: S2) -> Self {
+    self.to_table.push(ToTable::Table {
+      node:        node.into(),
+      table:       table.into(),
+      key_col:     None,
+      val_col:     None,
+      import_cols: None,
+    });
+    self
+  }
+
+  /// Import a {key: value} node as rows of key_col and val_col
+  pub fn to_key_value_table<
+    S1: Into<String>,
+    S2: Into<String>,
+    S3: Into<String>,
+    S4: Into<String>,
+  >(
+    mut self,
+    node: S1,
+    table: S2,
+    key_col: S3,
+    val_col: S4,
+  ) -> Self {
+    self.to_table.push(ToTable::Table {
+      node:        node.into(),
+      table:       table.into(),
+      key_col:     Some(key_col.into()),
+      val_col:     Some(val_col.into()),
+      import_cols: None,
+    });
+    self
+  }
+
+  pub fn to_keyvalue<S: Into<String>>(mut self, key: S) -> Self {
+    self.to_keyvalue.push(key.into());
+    self
+  }
+
+  pub fn to_json_table<S: Into<String>>(mut self, key: S) -> Self {
+    self.to_json_table.push(key.into());
+    self
+  }
+}
+
+impl Table {
+  /// Increase schema_changed to make ZeroNet rebuild the table
+  pub fn new(schema_changed: u64) -> Self {
+    Table {
+      cols: vec![],
+      indexes: vec![],
+      schema_changed,
+    }
+  }
+
+  pub fn col<S1: Into<String>, S2: Into<String>>(mut self, name: S1, sql_type: S2) -> Self {
+    self.cols.push((name.into(), sql_type.into()));
+    self
+  }
+
+  /// Add the json_id column linking rows to the json table, unless it exists
+  pub fn with_json_id(self) -> Self {
+    if self.cols.iter().any(|(name, _)| name == JSON_ID_COLUMN) {
+      self
+    } else {
+      self.col(JSON_ID_COLUMN, JSON_ID_TYPE)
+    }
+  }
+
+  /// Add a CREATE INDEX statement
+  pub fn index<S: Into<String>>(mut self, index: S) -> Self {
+    self.indexes.push(index.into());
+    self
+  }
+}
+
+/// Read and parse the site's dbschema.json
+pub async fn dbschema_get() -> Result<DbSchema, Error> {
+  let schema = file_get_string("dbschema.json", true, None)
+    .await?
+    .ok_or(Error::FalsyResponse)?;
+  Ok(serde_json::from_str(&schema)?)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::{json, Value};
+
+  crate::db_table! {
+    table = "topic", schema_changed = 2;
+    #[allow(dead_code)]
+    struct Topic {
+      topic_id: i64,
+      title: String,
+      body: Option<String>,
+      score: f64,
+    }
+  }
+
+  crate::db_table! {
+    table = "topic_vote", schema_changed = 1;
+    #[allow(dead_code)]
+    struct TopicVote {
+      topic_id: i64,
+      json_id: i64,
+    }
+  }
+
+  #[test]
+  fn schema_of_db_tables() {
+    let schema = DbSchema::new("ZeroTalk", "data/zerotalk.db")
+      .map(
+        "users/.+/data.json",
+        FileMap::new().to_table("topic").to_key_value_table(
+          "topic_vote",
+          "topic_vote",
+          "topic_id",
+          "vote",
+        ),
+      )
+      .map(
+        "users/.+/content.json",
+        FileMap::new().to_json_table("cert_user_id"),
+      )
+      .table_of::<Topic>()
+      .table(
+        TopicVote::TABLE,
+        TopicVote::table().index("CREATE INDEX topic_vote_key ON topic_vote(topic_id)"),
+      );
+    let expected = json!({
+      "db_name": "ZeroTalk",
+      "db_file": "data/zerotalk.db",
+      "version": 2,
+      "maps": {
+        "users/.+/content.json": {"to_json_table": ["cert_user_id"]},
+        "users/.+/data.json": {
+          "to_table": [
+            "topic",
+            {"node": "topic_vote", "table": "topic_vote", "key_col": "topic_id", "val_col": "vote"}
+          ]
+        }
+      },
+      "tables": {
+        "topic": {
+          "cols": [
+            ["topic_id", "INTEGER"],
+            ["title", "TEXT"],
+            ["body", "TEXT"],
+            ["score", "REAL"],
+            ["json_id", "INTEGER REFERENCES json (json_id)"]
+          ],
+          "indexes": [],
+          "schema_changed": 2
+        },
+        "topic_vote": {
+          "cols": [["topic_id", "INTEGER"], ["json_id", "INTEGER"]],
+          "indexes": ["CREATE INDEX topic_vote_key ON topic_vote(topic_id)"],
+          "schema_changed": 1
+        }
+      }
+    });
+    let json: Value = serde_json::from_str(&schema.to_json().unwrap()).unwrap();
+    assert_eq!(json, expected);
+  }
+
+  #[test]
+  fn table_without_schema_changed() {
+    let schema: DbSchema = serde_json::from_str(
+      r#"{"db_name": "Test", "db_file": "data/test.db", "version": 2,
+          "tables": {"topic": {"cols": [["topic_id", "INTEGER"]]}}}"#,
+    )
+    .unwrap();
+    let table = &schema.tables["topic"];
+    assert_eq!(table.schema_changed, 0);
+    assert!(table.indexes.is_empty());
+  }
+}
@@ -1,4 +1,5 @@
 use crate::responses::ErrorResponse;
+use crate::user_data::UserDataStage;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -11,6 +12,49 @@ pub enum ZeroFrameError {
   InvalidResponse,
   #[error("could not de/serialize object")]
   SerializationError(#[from] serde_json::Error),
+  #[error("could not decode base64")]
+  Base64Error(#[from] base64::DecodeError),
+  #[error("feed {feed} does not select column {column}")]
+  MissingFeedColumn { feed: String, column: String },
+  #[error("site has no permission for merged type {0}")]
+  MergedTypeNotAllowed(String),
+  #[error("{inner_path} does not match the allowed files {files_allowed}")]
+  FileNotAllowed {
+    inner_path:    String,
+    files_allowed: String,
+  },
+  #[error("size {size} exceeds the limit of {max_size} bytes")]
+  SizeLimitExceeded { size: u64, max_size: u64 },
+  #[error("invalid address {0}")]
+  InvalidAddress(String),
+  #[error("invalid cert_user_id {0}")]
+  InvalidCertUserId(String),
+  #[error("query parameter {0} uses the prefix reserved for generated parameters")]
+  ReservedQueryParam(String),
+  #[error("feed {0} can not use named query parameters")]
+  FeedNamedParams(String),
+  #[error("keyset column {0} is missing from the result rows")]
+  MissingKeysetColumn(String),
+  #[error("invalid pattern {0}")]
+  InvalidPattern(String),
+  #[error("invalid inner path {0}")]
+  InvalidInnerPath(String),
+  #[error("{0} is signed but could not be downloaded")]
+  FileNotDownloaded(String),
+  #[error("{inner_path} was signed again since it was read")]
+  WriteConflict {
+    inner_path: String,
+    expected:   f64,
+    found:      f64,
+  },
+  #[error("no user is logged in")]
+  NoAuthAddress,
+  #[error("could not {stage} user data")]
+  UserData {
+    stage:  UserDataStage,
+    #[source]
+    source: Box<ZeroFrameError>,
+  },
 }
 
 impl ZeroFrameError {
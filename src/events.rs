@@ -0,0 +1,62 @@
+use crate::responses::{SiteInfo, ZeroResponse};
+use crate::wrapper::register_request_handler;
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use std::cell::RefCell;
+use wasm_bindgen::JsValue;
+
+type Listeners<T> = RefCell<Option<Vec<UnboundedSender<T>>>>;
+type Handlers = RefCell<Vec<Box<dyn Fn(String, JsValue)>>>;
+
+thread_local! {
+  static SITE_INFO_LISTENERS: Listeners<SiteInfo> = const { RefCell::new(None) };
+  static SITE_INFO_HANDLERS: Handlers = RefCell::new(Vec::new());
+}
+
+/// Subscribe to the site info updates ZeroNet pushes with setSiteInfo
+/// Updates that can not be parsed as SiteInfo are skipped, on_site_info receives them as well.
+pub fn site_info_stream() -> UnboundedReceiver<SiteInfo> {
+  let (sender, receiver) = unbounded();
+  register_dispatch();
+  SITE_INFO_LISTENERS.with(|listeners| {
+    listeners
+      .borrow_mut()
+      .get_or_insert_with(Vec::new)
+      .push(sender)
+  });
+  receiver
+}
+
+/// Call a handler with the params of every setSiteInfo request
+/// The crate registers the only setSiteInfo handler with ZeroFrame, so its streams and the
+/// app's handlers all receive the updates. add_request_handler("setSiteInfo", ..) uses this.
+pub fn on_site_info<F: Fn(String, JsValue) + 'static>(handler: F) {
+  register_dispatch();
+  SITE_INFO_HANDLERS.with(|handlers| handlers.borrow_mut().push(Box::new(handler)));
+}
+
+fn register_dispatch() {
+  SITE_INFO_LISTENERS.with(|listeners| {
+    let mut listeners = listeners.borrow_mut();
+    if listeners.is_none() {
+      register_request_handler("setSiteInfo", dispatch_site_info);
+      *listeners = Some(Vec::new());
+    }
+  });
+}
+
+fn dispatch_site_info(cmd: String, params: JsValue) {
+  SITE_INFO_HANDLERS.with(|handlers| {
+    for handler in handlers.borrow().iter() {
+      handler(cmd.clone(), params.clone());
+    }
+  });
+  let site_info = match params.response::<SiteInfo>() {
+    Ok(site_info) => site_info,
+    Err(_) => return,
+  };
+  SITE_INFO_LISTENERS.with(|listeners| {
+    if let Some(listeners) = listeners.borrow_mut().as_mut() {
+      listeners.retain(|listener| listener.unbounded_send(site_info.clone()).is_ok());
+    }
+  });
+}
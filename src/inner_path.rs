@@ -0,0 +1,290 @@
+use crate::address::AuthAddress;
+use crate::ZeroFrameError as Error;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+
+const MAX_LENGTH: usize = 255;
+const RESERVED_NAMES: [&str; 6] = ["CON", "PRN", "AUX", "NUL", "CONOUT$", "CONIN$"];
+
+/// A path inside a site, validated with the same rules ZeroNet applies to signed files
+/// The empty path is the site root.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(try_from = "String", into = "String")]
+pub struct InnerPath(String);
+
+impl InnerPath {
+  pub fn root() -> Self {
+    InnerPath(String::new())
+  }
+
+  pub fn new<S: Into<String>>(inner_path: S) -> Result<Self, Error> {
+    let inner_path = inner_path.into();
+    validate(&inner_path)?;
+    Ok(InnerPath(inner_path))
+  }
+
+  /// The directory of a user, data/users/{auth_address}
+  pub fn user_directory(auth_address: &AuthAddress) -> Result<Self, Error> {
+    InnerPath::new(format!("data/users/{}", auth_address))
+  }
+
+  /// The path of a file in a merged site as seen from the merger site
+  pub fn merged(merged_type: &str, address: &str, inner_path: &InnerPath) -> Result<Self, Error> {
+    InnerPath::new(format!("merged-{}/{}", merged_type, address))?.join(inner_path.as_str())
+  }
+
+  pub fn as_str(&self) -> &str {
+    &self.0
+  }
+
+  pub fn is_root(&self) -> bool {
+    self.0.is_empty()
+  }
+
+  /// Append one or more segments, separated by slashes
+  pub fn join(&self, path: &str) -> Result<Self, Error> {
+    if self.is_root() {
+      InnerPath::new(path)
+    } else {
+      InnerPath::new(format!("{}/{}", self.0, path))
+    }
+  }
+
+  /// The containing directory, None for the site root
+  pub fn parent(&self) -> Option<Self> {
+    if self.is_root() {
+      return None;
+    }
+    match self.0.rfind('/') {
+      Some(index) => Some(InnerPath(self.0[..index].to_string())),
+      None => Some(InnerPath::root()),
+    }
+  }
+
+  pub fn file_name(&self) -> Option<&str> {
+    self.0.rsplit('/').next().filter(|name| !name.is_empty())
+  }
+
+  /// The content.json in the same directory
+  pub fn content_json(&self) -> Self {
+    let directory = self.parent().unwrap_or_default();
+    InnerPath(match directory.is_root() {
+      true => "content.json".to_string(),
+      false => format!("{}/content.json", directory),
+    })
+  }
+
+  /// The path relative to a directory, if it is inside of it
+  pub fn strip_prefix(&self, directory: &InnerPath) -> Option<&str> {
+    if directory.is_root() {
+      return Some(&self.0);
+    }
+    self
+      .0
+      .strip_prefix(directory.as_str())
+      .and_then(|path| path.strip_prefix('/'))
+  }
+}
+
+fn validate(inner_path: &str) -> Result<(), Error> {
+  let invalid = |reason: &str| {
+    Err(Error::InvalidInnerPath(format!(
+      "{}: {}",
+      inner_path, reason
+    )))
+  };
+  if inner_path.is_empty() {
+    return Ok(());
+  }
+  if inner_path.len() > MAX_LENGTH {
+    return invalid("too long");
+  }
+  if inner_path.starts_with('/') {
+    return invalid("absolute paths are not allowed");
+  }
+  if inner_path.ends_with('.') || inner_path.ends_with(' ') {
+    return invalid("can not end with a dot or space");
+  }
+  if let Some(c) = inner_path
+    .chars()
+    .find(|c| c.is_control() || "\"*:<>?\\|".contains(*c))
+  {
+    return invalid(&format!("invalid character {:?}", c));
+  }
+  for segment in inner_path.split('/') {
+    if segment.is_empty() {
+      return invalid("empty segment");
+    }
+    if segment == ".." {
+      return invalid("parent directory segments are not allowed");
+    }
+    let name = segment.split('.').next().unwrap_or_default().to_uppercase();
+    let reserved = RESERVED_NAMES.contains(&name.as_str())
+      || ((name.starts_with("COM") || name.starts_with("LPT"))
+        && name.len() == 4
+        && name.ends_with(|c: char| ('1'..='9').contains(&c)));
+    if reserved {
+      return invalid("reserved file name");
+    }
+  }
+  Ok(())
+}
+
+impl fmt::Display for InnerPath {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(&self.0)
+  }
+}
+
+impl AsRef<str> for InnerPath {
+  fn as_ref(&self) -> &str {
+    &self.0
+  }
+}
+
+impl TryFrom<&str> for InnerPath {
+  type Error = Error;
+
+  fn try_from(inner_path: &str) -> Result<Self, Self::Error> {
+    InnerPath::new(inner_path)
+  }
+}
+
+impl TryFrom<String> for InnerPath {
+  type Error = Error;
+
+  fn try_from(inner_path: String) -> Result<Self, Self::Error> {
+    InnerPath::new(inner_path)
+  }
+}
+
+impl From<InnerPath> for String {
+  fn from(inner_path: InnerPath) -> Self {
+    inner_path.0
+  }
+}
+
+/// Anything the file APIs accept as an inner path, strings are validated on use
+pub trait IntoInnerPath {
+  fn into_inner_path(self) -> Result<InnerPath, Error>;
+}
+
+impl IntoInnerPath for InnerPath {
+  fn into_inner_path(self) -> Result<InnerPath, Error> {
+    Ok(self)
+  }
+}
+
+impl IntoInnerPath for &InnerPath {
+  fn into_inner_path(self) -> Result<InnerPath, Error> {
+    Ok(self.clone())
+  }
+}
+
+impl IntoInnerPath for &str {
+  fn into_inner_path(self) -> Result<InnerPath, Error> {
+    InnerPath::new(self)
+  }
+}
+
+impl IntoInnerPath for String {
+  fn into_inner_path(self) -> Result<InnerPath, Error> {
+    InnerPath::new(self)
+  }
+}
+
+impl IntoInnerPath for &String {
+  fn into_inner_path(self) -> Result<InnerPath, Error> {
+    InnerPath::new(self.as_str())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn validate_accepts_site_paths() {
+    assert!(InnerPath::new("").unwrap().is_root());
+    assert!(InnerPath::new("content.json").is_ok());
+    assert!(InnerPath::new("data/users/1A/data.json").is_ok());
+    assert!(InnerPath::new("img/.hidden").is_ok());
+  }
+
+  #[test]
+  fn validate_rejects_invalid_paths() {
+    for inner_path in [
+      "/content.json",
+      "data//data.json",
+      "data/",
+      "../content.json",
+      "data/../content.json",
+      "data.",
+      "data ",
+      "data/a:b.json",
+      "data/a?.json",
+      "data/a\\b.json",
+      "data/\n.json",
+      "CON",
+      "data/nul.txt",
+      "data/COM1.json",
+      "data/lpt9",
+    ]
+    .iter()
+    {
+      assert!(InnerPath::new(*inner_path).is_err(), "{:?}", inner_path);
+    }
+    assert!(InnerPath::new("a".repeat(MAX_LENGTH + 1)).is_err());
+    assert!(InnerPath::new("data/COM10.json").is_ok());
+  }
+
+  #[test]
+  fn join() {
+    let root = InnerPath::root();
+    assert_eq!(root.join("data").unwrap().as_str(), "data");
+    let data = InnerPath::new("data").unwrap();
+    assert_eq!(data.join("users/1A").unwrap().as_str(), "data/users/1A");
+    assert!(data.join("../content.json").is_err());
+    assert!(data.join("").is_err());
+  }
+
+  #[test]
+  fn parent() {
+    let path = InnerPath::new("data/users/data.json").unwrap();
+    assert_eq!(path.parent().unwrap().as_str(), "data/users");
+    assert!(InnerPath::new("content.json")
+      .unwrap()
+      .parent()
+      .unwrap()
+      .is_root());
+    assert!(InnerPath::root().parent().is_none());
+  }
+
+  #[test]
+  fn content_json() {
+    let path = InnerPath::new("data/users/1A/data.json").unwrap();
+    assert_eq!(path.content_json().as_str(), "data/users/1A/content.json");
+    let path = InnerPath::new("index.html").unwrap();
+    assert_eq!(path.content_json().as_str(), "content.json");
+  }
+
+  #[test]
+  fn strip_prefix() {
+    let path = InnerPath::new("data/users/1A/data.json").unwrap();
+    let directory = InnerPath::new("data/users").unwrap();
+    assert_eq!(path.strip_prefix(&directory), Some("1A/data.json"));
+    let other = InnerPath::new("data/use").unwrap();
+    assert_eq!(path.strip_prefix(&other), None);
+  }
+
+  #[test]
+  fn merged() {
+    let path = InnerPath::new("data/users/1A/data.json").unwrap();
+    let merged = InnerPath::merged("ZeroMe", "1Hub", &path).unwrap();
+    assert_eq!(
+      merged.as_str(),
+      "merged-ZeroMe/1Hub/data/users/1A/data.json"
+    );
+  }
+}
@@ -1,27 +1,45 @@
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::{closure::Closure, JsValue};
 
+pub mod address;
 pub mod admin;
 pub mod bigfile;
 pub mod chart;
+pub mod content;
 pub mod cors;
 pub mod crypt_message;
+pub mod cursor;
+pub mod dbschema;
 pub mod error;
+pub mod events;
+pub mod inner_path;
+pub mod live_query;
 pub mod merger_site;
 pub mod multiuser;
 pub mod mute;
 pub mod newsfeed;
 pub mod optional_manager;
+pub mod private_message;
+pub mod publish;
+pub mod query;
 pub mod responses;
 pub mod ui_server;
+pub mod user_data;
+pub mod versioned;
 pub mod wrapper;
+pub mod write_queue;
 
+pub use address::{AuthAddress, CertUserId, SiteAddress};
 pub use error::ZeroFrameError;
-pub use wrapper::{NotificationType, PromptType};
+pub use inner_path::InnerPath;
+pub use wrapper::{NotificationType, Permission, PromptType};
 
 #[wasm_bindgen(module = "/js/zeroframe.js")]
 extern "C" {
   fn cmd(cmd: &str, params: Vec<JsValue>);
   async fn cmdp(cmd: &str, params: Vec<JsValue>) -> JsValue;
   fn on_request(cmd: &str, handler: &Closure<dyn Fn(String, JsValue)>);
+  async fn sleep(ms: f64) -> JsValue;
+  #[wasm_bindgen(catch)]
+  fn new_regexp(pattern: &str, flags: &str) -> Result<js_sys::RegExp, JsValue>;
 }
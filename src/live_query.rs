@@ -0,0 +1,167 @@
+use super::sleep;
+use crate::content::regexp;
+use crate::events::site_info_stream;
+use crate::query::QueryParams;
+use crate::responses::{SiteEvent, SiteInfo};
+use crate::ui_server::{db_query, file_get_string};
+use crate::ZeroFrameError as Error;
+use futures::channel::mpsc::UnboundedReceiver;
+use futures::future::{self, Either};
+use futures::stream::{self, Stream, StreamExt};
+use js_sys::RegExp;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+pub const DEFAULT_DEBOUNCE: usize = 500;
+
+/// A query that is re-run whenever a json file feeding the database is updated
+///
+/// By default the update is relevant if the file matches one of the maps of dbschema.json,
+/// use path_filter to only react to specific files instead.
+#[derive(Clone, Debug)]
+pub struct LiveQuery {
+  sql:          String,
+  params:       QueryParams,
+  debounce:     usize,
+  path_filters: Vec<String>,
+}
+
+struct LiveQueryState {
+  query:   LiveQuery,
+  updates: UnboundedReceiver<SiteInfo>,
+  matcher: Option<PathMatcher>,
+  started: bool,
+}
+
+/// Matches inner paths against regex patterns, relative to a directory
+struct PathMatcher {
+  directory: String,
+  patterns:  Vec<RegExp>,
+}
+
+/// Stream the results of a query, and fresh results after every relevant file update
+pub fn live_query<T: DeserializeOwned, S: Into<String>, P: Into<QueryParams>>(
+  sql: S,
+  params: P,
+) -> impl Stream<Item = Result<Vec<T>, Error>> {
+  LiveQuery::new(sql, params).stream()
+}
+
+impl LiveQuery {
+  pub fn new<S: Into<String>, P: Into<QueryParams>>(sql: S, params: P) -> Self {
+    LiveQuery {
+      sql:          sql.into(),
+      params:       params.into(),
+      debounce:     DEFAULT_DEBOUNCE,
+      path_filters: vec![],
+    }
+  }
+
+  /// Wait until no updates arrived for this many ms before re-running the query
+  pub fn debounce(mut self, debounce: usize) -> Self {
+    self.debounce = debounce;
+    self
+  }
+
+  /// Only re-run for files whose inner path matches the regex pattern
+  pub fn path_filter<S: Into<String>>(mut self, pattern: S) -> Self {
+    self.path_filters.push(pattern.into());
+    self
+  }
+
+  /// Emit the current results, followed by new results after every burst of relevant updates
+  /// The stream ends when the setSiteInfo subscription is closed,
+  /// or after the error if the path patterns could not be loaded
+  pub fn stream<T: DeserializeOwned>(self) -> impl Stream<Item = Result<Vec<T>, Error>> {
+    let state = LiveQueryState {
+      query:   self,
+      updates: site_info_stream(),
+      matcher: None,
+      started: false,
+    };
+    stream::unfold(state, |mut state| async move {
+      if !state.started {
+        state.started = true;
+        match PathMatcher::load(&state.query.path_filters).await {
+          Ok(matcher) => state.matcher = Some(matcher),
+          Err(err) => return Some((Err(err), state)),
+        }
+      } else if state.matcher.is_none() || !state.wait_for_update().await {
+        return None;
+      }
+      let query = &state.query;
+      let result = db_query::<T>(query.sql.clone(), query.params.clone()).await;
+      Some((result, state))
+    })
+  }
+}
+
+impl LiveQueryState {
+  fn is_relevant(&self, site_info: &SiteInfo) -> bool {
+    match (&site_info.event, &self.matcher) {
+      (Some(SiteEvent::FileDone(inner_path)), Some(matcher)) => matcher.matches(inner_path),
+      _ => false,
+    }
+  }
+
+  /// Wait for a relevant update and the end of its burst, returns false if updates stopped
+  async fn wait_for_update(&mut self) -> bool {
+    loop {
+      match self.updates.next().await {
+        Some(site_info) if self.is_relevant(&site_info) => break,
+        Some(_) => continue,
+        None => return false,
+      }
+    }
+    loop {
+      let timeout = Box::pin(sleep(self.query.debounce as f64));
+      match future::select(self.updates.next(), timeout).await {
+        Either::Left((Some(_), _)) => continue,
+        Either::Left((None, _)) | Either::Right(_) => return true,
+      }
+    }
+  }
+}
+
+impl PathMatcher {
+  /// Use the given patterns, or the maps of dbschema.json relative to the database directory
+  async fn load(path_filters: &[String]) -> Result<Self, Error> {
+    if !path_filters.is_empty() {
+      return PathMatcher::new("", path_filters.iter().map(String::as_str));
+    }
+    let schema: Value = match file_get_string("dbschema.json", false, None).await? {
+      Some(schema) => serde_json::from_str(&schema)?,
+      None => return PathMatcher::new("", vec![r"\.json$"]),
+    };
+    let db_file = schema["db_file"].as_str().unwrap_or_default();
+    let directory = match db_file.rfind('/') {
+      Some(index) => &db_file[..=index],
+      None => "",
+    };
+    let patterns: Vec<&str> = schema["maps"]
+      .as_object()
+      .map(|maps| maps.keys().map(String::as_str).collect())
+      .unwrap_or_default();
+    PathMatcher::new(directory, patterns)
+  }
+
+  fn new<'a, I: IntoIterator<Item = &'a str>>(directory: &str, patterns: I) -> Result<Self, Error> {
+    Ok(PathMatcher {
+      directory: directory.to_string(),
+      patterns:  patterns
+        .into_iter()
+        .map(|pattern| regexp(&format!("^(?:{})", pattern)))
+        .collect::<Result<_, _>>()?,
+    })
+  }
+
+  fn matches(&self, inner_path: &str) -> bool {
+    match inner_path.strip_prefix(&self.directory) {
+      Some(relative_path) => self
+        .patterns
+        .iter()
+        .any(|pattern| pattern.test(relative_path)),
+      None => false,
+    }
+  }
+}
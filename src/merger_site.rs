@@ -1,29 +1,197 @@
-use super::{cmd, cmdp};
-use crate::responses::SiteInfo;
+use super::{cmdp, sleep};
+use crate::address::AuthAddress;
+use crate::content::owning_content_json;
+use crate::inner_path::{InnerPath, IntoInnerPath};
+use crate::responses::{MergedSite, SiteInfo, ZeroResponse};
+use crate::ui_server::{file_write_bytes, file_write_string, site_info, site_publish, site_sign};
+use crate::wrapper::Permission;
 use crate::ZeroFrameError as Error;
 use std::collections::HashMap;
+use std::fmt;
 use wasm_bindgen::prelude::*;
 
-/// Start downloading new merger site(s)
-pub fn merger_site_add(addresses: Vec<String>) {
-  cmd(
-    "mergerSiteAdd",
-    vec![JsValue::from_serde(&addresses).unwrap()],
-  );
+/// Merged types the site is allowed to access
+pub fn merged_types(site_info: &SiteInfo) -> Vec<String> {
+  let mut merged_types: Vec<String> = site_info
+    .settings
+    .permissions
+    .iter()
+    .filter_map(|permission| match permission.parse() {
+      Ok(Permission::Merger(merged_type)) => Some(merged_type),
+      _ => None,
+    })
+    .collect();
+  let own_type = &site_info.content.merged_type;
+  if !own_type.is_empty() && !merged_types.contains(own_type) {
+    merged_types.push(own_type.clone());
+  }
+  merged_types
+}
+
+async fn merged_type_of(address: &str) -> Result<String, Error> {
+  merger_site_list(None)
+    .await?
+    .remove(address)
+    .ok_or_else(|| Error::RemoteError(format!("No merged site found: {}", address)))
+}
+
+/// Return an error unless the current site may access the merged type
+pub async fn validate_merged_type(merged_type: &str) -> Result<(), Error> {
+  let site_info = site_info().await?;
+  if merged_types(&site_info)
+    .iter()
+    .any(|allowed| allowed == merged_type)
+  {
+    Ok(())
+  } else {
+    Err(Error::MergedTypeNotAllowed(merged_type.to_string()))
+  }
+}
+
+/// How often merger_site_add checks whether the added sites are listed, in ms
+pub const MERGER_SITE_ADD_POLL_INTERVAL: usize = 1000;
+
+/// Start downloading new merged site(s) and wait until mergerSiteList includes them
+/// ZeroNet replies before the user confirmed and does not report a declined dialog,
+/// returns Ok(false) if the sites are not listed within the timeout (in ms).
+pub async fn merger_site_add(addresses: Vec<String>, timeout: usize) -> Result<bool, Error> {
+  let site_info = site_info().await?;
+  if merged_types(&site_info).is_empty() {
+    return Err(Error::MergedTypeNotAllowed("*".to_string()));
+  }
+  let response = cmdp("mergerSiteAdd", vec![JsValue::from_serde(&addresses)?]).await;
+  response.result()?;
+  let deadline = js_sys::Date::now() + timeout as f64;
+  loop {
+    let sites = merger_site_list(None).await?;
+    if addresses.iter().all(|address| sites.contains_key(address)) {
+      return Ok(true);
+    }
+    if js_sys::Date::now() >= deadline {
+      return Ok(false);
+    }
+    sleep(MERGER_SITE_ADD_POLL_INTERVAL as f64).await;
+  }
 }
 
 /// Stop seeding and delete a merged site.
-pub fn merger_site_delete(address: &str) {
-  cmd("mergerSiteDelete", vec![JsValue::from_str(address)]);
+/// Fails with MergedTypeNotAllowed unless the site may access the merged site's type
+pub async fn merger_site_delete(address: &str) -> Result<(), Error> {
+  let merged_type = merged_type_of(address).await?;
+  validate_merged_type(&merged_type).await?;
+  let response = cmdp("mergerSiteDelete", vec![JsValue::from_str(address)]).await;
+  response.result()
 }
 
-/// Return merged sites
-pub async fn merger_site_list() -> Result<Vec<String>, Error> {
+/// Return the addresses of merged sites with their merged type
+pub async fn merger_site_list(merged_type: Option<&str>) -> Result<HashMap<String, String>, Error> {
+  if let Some(merged_type) = merged_type {
+    validate_merged_type(merged_type).await?;
+  }
   let response = cmdp("mergerSiteList", vec![JsValue::from_bool(false)]).await;
-  Ok(response.into_serde()?)
+  let mut sites = response.response::<HashMap<String, String>>()?;
+  if let Some(merged_type) = merged_type {
+    sites.retain(|_, site_type| site_type == merged_type);
+  }
+  Ok(sites)
 }
 
-pub async fn merger_site_info_list() -> Result<HashMap<String, SiteInfo>, Error> {
+/// Return the site info of merged sites
+pub async fn merger_site_info_list(
+  merged_type: Option<&str>,
+) -> Result<HashMap<String, MergedSite>, Error> {
+  if let Some(merged_type) = merged_type {
+    validate_merged_type(merged_type).await?;
+  }
   let response = cmdp("mergerSiteList", vec![JsValue::from_bool(true)]).await;
-  Ok(response.into_serde()?)
+  let mut sites = response.response::<HashMap<String, MergedSite>>()?;
+  if let Some(merged_type) = merged_type {
+    sites.retain(|_, site| site.merged_type == merged_type);
+  }
+  Ok(sites)
+}
+
+/// A file inside a merged site, addressed from the merger site
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MergedPath {
+  pub merged_type: String,
+  pub address:     String,
+  pub inner_path:  InnerPath,
+}
+
+impl MergedPath {
+  pub fn new<S1: Into<String>, S2: Into<String>, P: IntoInnerPath>(
+    merged_type: S1,
+    address: S2,
+    inner_path: P,
+  ) -> Result<Self, Error> {
+    let path = MergedPath {
+      merged_type: merged_type.into(),
+      address:     address.into(),
+      inner_path:  inner_path.into_inner_path()?,
+    };
+    path.to_inner_path()?;
+    Ok(path)
+  }
+
+  /// Look up the merged type of a downloaded merged site
+  pub async fn lookup<P: IntoInnerPath>(address: &str, inner_path: P) -> Result<Self, Error> {
+    let merged_type = merged_type_of(address).await?;
+    MergedPath::new(merged_type, address, inner_path)
+  }
+
+  /// Path of a file in the user's directory of a merged site
+  pub fn user_file<S1: Into<String>, S2: Into<String>>(
+    merged_type: S1,
+    address: S2,
+    auth_address: &AuthAddress,
+    file_name: &str,
+  ) -> Result<Self, Error> {
+    let inner_path = InnerPath::user_directory(auth_address)?.join(file_name)?;
+    MergedPath::new(merged_type, address, inner_path)
+  }
+
+  /// The content.json that signs the file, which has to be signed after a change
+  pub async fn content_json(&self) -> Result<Self, Error> {
+    let site_root = InnerPath::new(format!("merged-{}/{}", self.merged_type, self.address))?;
+    let content_inner_path = owning_content_json(&self.to_inner_path()?).await?;
+    let inner_path = content_inner_path
+      .strip_prefix(&site_root)
+      .unwrap_or("content.json");
+    MergedPath::new(&*self.merged_type, &*self.address, inner_path)
+  }
+
+  /// The path as seen from the merger site, merged-{merged_type}/{address}/{inner_path}
+  pub fn to_inner_path(&self) -> Result<InnerPath, Error> {
+    InnerPath::merged(&self.merged_type, &self.address, &self.inner_path)
+  }
+}
+
+impl fmt::Display for MergedPath {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "merged-{}/{}/{}",
+      self.merged_type, self.address, self.inner_path
+    )
+  }
+}
+
+/// Write a text file to a merged site and sign and publish its content.json
+pub async fn merged_file_write_string(path: &MergedPath, content: String) -> Result<(), Error> {
+  file_write_string(path.to_inner_path()?, content).await?;
+  merged_sign_publish(path).await
+}
+
+/// Write a binary file to a merged site and sign and publish its content.json
+pub async fn merged_file_write_bytes(path: &MergedPath, content: Vec<u8>) -> Result<(), Error> {
+  file_write_bytes(path.to_inner_path()?, content).await?;
+  merged_sign_publish(path).await
+}
+
+/// Sign and publish the content.json that covers the given file of a merged site
+pub async fn merged_sign_publish(path: &MergedPath) -> Result<(), Error> {
+  let content_json = path.content_json().await?.to_inner_path()?;
+  site_sign(None, Some(content_json.clone()), false).await?;
+  site_publish(None, Some(content_json), false).await
 }
@@ -1,14 +1,20 @@
 use super::cmdp;
-use crate::responses::ZeroResponse;
+use crate::address::{AuthAddress, CertUserId};
+use crate::responses::{MuteEntry, ZeroResponse};
 use crate::ZeroFrameError as Error;
+use std::collections::HashMap;
 use wasm_bindgen::prelude::*;
 
-pub async fn mute_add(auth_address: &str, cert_user_id: &str, reason: &str) -> Result<(), Error> {
+pub async fn mute_add(
+  auth_address: &AuthAddress,
+  cert_user_id: &CertUserId,
+  reason: &str,
+) -> Result<(), Error> {
   let response = cmdp(
     "muteAdd",
     vec![
-      JsValue::from_str(auth_address),
-      JsValue::from_str(cert_user_id),
+      JsValue::from_str(auth_address.as_str()),
+      JsValue::from_str(&cert_user_id.to_string()),
       JsValue::from_str(reason),
     ],
   )
@@ -16,12 +22,13 @@ pub async fn mute_add(auth_address: &str, cert_user_id: &str, reason: &str) -> R
   response.result()
 }
 
-pub async fn mute_remove(auth_address: &str) -> Result<(), Error> {
-  let response = cmdp("muteRemove", vec![JsValue::from_str(auth_address)]).await;
+pub async fn mute_remove(auth_address: &AuthAddress) -> Result<(), Error> {
+  let response = cmdp("muteRemove", vec![JsValue::from_str(auth_address.as_str())]).await;
   response.result()
 }
 
-pub async fn mute_list() -> Result<Vec<String>, Error> {
+/// The muted users by auth_address
+pub async fn mute_list() -> Result<HashMap<AuthAddress, MuteEntry>, Error> {
   let response = cmdp("muteList", vec![]).await;
-  response.response::<Vec<String>>()
+  response.response::<HashMap<AuthAddress, MuteEntry>>()
 }
@@ -1,29 +1,151 @@
 use super::cmdp;
-use crate::responses::ZeroResponse;
+use crate::query::Query;
+use crate::responses::{FeedQueryResult, ZeroResponse};
 use crate::ZeroFrameError as Error;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
 use wasm_bindgen::prelude::*;
 
+/// Columns every feed query has to select
+pub const FEED_COLUMNS: [&str; 5] = ["type", "date_added", "title", "body", "url"];
+
+/// A followed feed, serialized as [query, params]
+/// The Newsfeed plugin quotes the params and splices them into the query at `:params`,
+/// it does not bind named parameters.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Feed(pub String, #[serde(default)] pub Vec<Value>);
+
+impl Feed {
+  pub fn query(&self) -> &str {
+    &self.0
+  }
+
+  pub fn params(&self) -> &[Value] {
+    &self.1
+  }
+
+  /// Check that the query selects all of the FEED_COLUMNS
+  pub fn validate(&self, name: &str) -> Result<(), Error> {
+    let query = self.0.to_lowercase();
+    let words: Vec<&str> = query
+      .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+      .collect();
+    for column in FEED_COLUMNS.iter() {
+      if !words.contains(column) {
+        return Err(Error::MissingFeedColumn {
+          feed:   name.to_string(),
+          column: column.to_string(),
+        });
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Builder for the argument of feed_follow
+#[derive(Default)]
+pub struct FeedFollow {
+  feeds: HashMap<String, Feed>,
+}
+
+impl FeedFollow {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Start from the currently followed feeds
+  pub fn from_feeds(feeds: HashMap<String, Feed>) -> Self {
+    FeedFollow { feeds }
+  }
+
+  pub fn feed<S1: Into<String>, S2: Into<String>>(mut self, name: S1, query: S2) -> Self {
+    self.feeds.insert(name.into(), Feed(query.into(), vec![]));
+    self
+  }
+
+  /// Follow a query using `:params`, for example `WHERE topic_id IN :params`
+  pub fn feed_with_params<S1: Into<String>, S2: Into<String>, V: Serialize>(
+    mut self,
+    name: S1,
+    query: S2,
+    params: &[V],
+  ) -> Result<Self, Error> {
+    let params = params
+      .iter()
+      .map(serde_json::to_value)
+      .collect::<Result<Vec<Value>, _>>()?;
+    self.feeds.insert(name.into(), Feed(query.into(), params));
+    Ok(self)
+  }
+
+  /// Follow a query built with the query builder, it has to select the FEED_COLUMNS
+  /// The Newsfeed plugin does not bind named parameters, so the query can not have conditions
+  /// with generated parameters. Use feed_with_params for queries that need `:params`.
+  pub fn feed_query<S: Into<String>>(mut self, name: S, query: Query) -> Result<Self, Error> {
+    let name = name.into();
+    let (sql, params) = query.build();
+    if !params.is_empty() {
+      return Err(Error::FeedNamedParams(name));
+    }
+    self.feeds.insert(name, Feed(sql, vec![]));
+    Ok(self)
+  }
+
+  pub fn remove(mut self, name: &str) -> Self {
+    self.feeds.remove(name);
+    self
+  }
+
+  /// Validate all queries and return the feeds
+  pub fn build(self) -> Result<HashMap<String, Feed>, Error> {
+    for (name, feed) in self.feeds.iter() {
+      feed.validate(name)?;
+    }
+    Ok(self.feeds)
+  }
+}
+
 /// Set followed SQL queries.
-pub async fn feed_follow(query: &str) -> Result<(), Error> {
-  let response = cmdp("feedFollow", vec![JsValue::from_str(query)]).await;
+pub async fn feed_follow(feeds: FeedFollow) -> Result<(), Error> {
+  let feeds = feeds.build()?;
+  let response = cmdp("feedFollow", vec![JsValue::from_serde(&feeds)?]).await;
   response.result()
 }
 
-// TODO: no JsValue
 /// Return currently followed feeds
-pub async fn feed_list_follow() -> JsValue {
-  cmdp("feedListFollow", vec![]).await
+pub async fn feed_list_follow() -> Result<HashMap<String, Feed>, Error> {
+  let response = cmdp("feedListFollow", vec![]).await;
+  response.response::<HashMap<String, Feed>>()
 }
 
-// TODO: find solution without JsValue
 /// Execute all queries for followed sites in the user's notifications feed
-pub async fn feed_query(limit: usize, day_limit: usize) -> JsValue {
-  cmdp(
+pub async fn feed_query(limit: usize, day_limit: usize) -> Result<FeedQueryResult, Error> {
+  let response = cmdp(
     "feedQuery",
     vec![
       JsValue::from_f64(limit as f64),
       JsValue::from_f64(day_limit as f64),
     ],
   )
-  .await
+  .await;
+  response.response::<FeedQueryResult>()
+}
+
+/// Search the feeds of all sites for the given text
+pub async fn feed_search(
+  search: &str,
+  limit: usize,
+  day_limit: usize,
+) -> Result<FeedQueryResult, Error> {
+  let response = cmdp(
+    "feedSearch",
+    vec![
+      JsValue::from_str(search),
+      JsValue::from_f64(limit as f64),
+      JsValue::from_f64(day_limit as f64),
+    ],
+  )
+  .await;
+  response.response::<FeedQueryResult>()
 }
@@ -1,4 +1,5 @@
 use super::{cmd, cmdp};
+use crate::inner_path::InnerPath;
 use wasm_bindgen::prelude::*;
 
 pub async fn optional_file_list(
@@ -17,20 +18,24 @@ pub async fn optional_file_list(
   .await
 }
 
-pub async fn optional_file_info(inner_path: &str) -> JsValue {
-  cmdp("optionalFileInfo", vec![JsValue::from_str(inner_path)]).await
+pub async fn optional_file_info(inner_path: &InnerPath) -> JsValue {
+  cmdp(
+    "optionalFileInfo",
+    vec![JsValue::from_str(inner_path.as_str())],
+  )
+  .await
 }
 
-pub fn optional_file_pin(inner_path: &str, address: Option<String>) {
-  let mut params = vec![JsValue::from_str(inner_path)];
+pub fn optional_file_pin(inner_path: &InnerPath, address: Option<String>) {
+  let mut params = vec![JsValue::from_str(inner_path.as_str())];
   if let Some(address) = address {
     params.push(JsValue::from_str(&address));
   }
   cmd("optionalFilePin", params)
 }
 
-pub fn optional_file_unpin(inner_path: &str, address: Option<String>) {
-  let mut params = vec![JsValue::from_str(inner_path)];
+pub fn optional_file_unpin(inner_path: &InnerPath, address: Option<String>) {
+  let mut params = vec![JsValue::from_str(inner_path.as_str())];
   if let Some(address) = address {
     params.push(JsValue::from_str(&address));
   }
@@ -49,16 +54,19 @@ pub async fn optional_help_list(address: &str) -> JsValue {
   cmdp("optionalHelpList", vec![JsValue::from_str(address)]).await
 }
 
-pub fn optional_help(directory: &str, title: &str, address: Option<String>) {
-  let mut params = vec![JsValue::from_str(directory), JsValue::from_str(title)];
+pub fn optional_help(directory: &InnerPath, title: &str, address: Option<String>) {
+  let mut params = vec![
+    JsValue::from_str(directory.as_str()),
+    JsValue::from_str(title),
+  ];
   if let Some(address) = address {
     params.push(JsValue::from(&address));
   }
   cmd("optionalHelp", params)
 }
 
-pub fn optional_help_remove(directory: &str, address: Option<String>) {
-  let mut params = vec![JsValue::from_str(directory)];
+pub fn optional_help_remove(directory: &InnerPath, address: Option<String>) {
+  let mut params = vec![JsValue::from_str(directory.as_str())];
   if let Some(address) = address {
     params.push(JsValue::from_str(&address));
   }
@@ -0,0 +1,243 @@
+use crate::crypt_message::{
+  aes_decrypt_multiple, aes_encrypt, ecies_decrypt_multiple, ecies_encrypt_to,
+};
+use crate::inner_path::IntoInnerPath;
+use crate::responses::{AesEncrypted, Base64, PublicKey};
+use crate::ui_server::{file_get_string, file_query, file_write_string};
+use crate::ZeroFrameError as Error;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Key under which envelopes are stored in a user's data.json
+pub const MESSAGES_KEY: &str = "messages";
+
+/// A message encrypted once with AES, with the AES key wrapped for every recipient via ECIES
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Envelope {
+  pub iv:         Base64,
+  pub ciphertext: Base64,
+  pub keys:       Vec<Base64>,
+  pub date_added: u64,
+}
+
+/// A decrypted message from a user's inbox
+#[derive(Clone, Debug)]
+pub struct Message {
+  /// Inner path of the data.json the envelope was stored in
+  pub inner_path: String,
+  pub date_added: u64,
+  pub text:       String,
+}
+
+#[derive(Deserialize)]
+struct StoredEnvelope {
+  #[serde(flatten)]
+  envelope:   Envelope,
+  inner_path: String,
+}
+
+/// Encrypt a message for the given recipients
+/// Include the sender's own public key to be able to read sent messages
+pub async fn encrypt(text: &str, recipients: &[PublicKey]) -> Result<Envelope, Error> {
+  let encrypted = aes_encrypt(text, None).await?;
+  let mut keys = Vec::with_capacity(recipients.len());
+  for recipient in recipients {
+    let wrapped = ecies_encrypt_to(encrypted.key.as_str(), recipient, false).await?;
+    keys.push(wrapped.ciphertext);
+  }
+  Ok(Envelope::new(
+    encrypted,
+    keys,
+    (js_sys::Date::now() / 1000.0) as u64,
+  ))
+}
+
+impl Envelope {
+  /// Wrap an AES encrypted text, keys are its AES key encrypted for every recipient
+  pub fn new(encrypted: AesEncrypted, keys: Vec<Base64>, date_added: u64) -> Self {
+    Envelope {
+      iv: encrypted.iv,
+      ciphertext: encrypted.ciphertext,
+      keys,
+      date_added,
+    }
+  }
+}
+
+/// Decrypt a message, returns Ok(None) if it was not addressed to the user
+pub async fn decrypt(
+  envelope: &Envelope,
+  privatekey_index: usize,
+) -> Result<Option<String>, Error> {
+  let mut texts = decrypt_multiple(std::slice::from_ref(envelope), privatekey_index).await?;
+  Ok(texts.pop().flatten())
+}
+
+/// Decrypt multiple messages, unwrapping the keys and decrypting the texts in one request each
+/// Messages not addressed to the user, or that fail to decrypt, are returned as None
+pub async fn decrypt_multiple(
+  envelopes: &[Envelope],
+  privatekey_index: usize,
+) -> Result<Vec<Option<String>>, Error> {
+  let wrapped_keys: Vec<Base64> = envelopes
+    .iter()
+    .flat_map(|envelope| envelope.keys.iter().cloned())
+    .collect();
+  let unwrapped = ecies_decrypt_multiple(&wrapped_keys, privatekey_index).await?;
+  let AesRequest {
+    addressed,
+    encrypted,
+    aes_keys,
+  } = AesRequest::new(envelopes, unwrapped);
+  let mut decrypted = match encrypted.is_empty() {
+    true => vec![],
+    false => aes_decrypt_multiple(&encrypted, &aes_keys).await?,
+  }
+  .into_iter();
+  Ok(
+    addressed
+      .into_iter()
+      .map(|addressed| match addressed {
+        true => decrypted.next().flatten(),
+        false => None,
+      })
+      .collect(),
+  )
+}
+
+/// The texts of the envelopes addressed to the user, with the AES keys to decrypt them
+#[derive(Debug, PartialEq)]
+struct AesRequest {
+  addressed: Vec<bool>,
+  encrypted: Vec<(Base64, Base64)>,
+  aes_keys:  Vec<Base64>,
+}
+
+impl AesRequest {
+  /// Pair the unwrapped keys, in the order of all envelopes' keys, with their envelopes
+  fn new(envelopes: &[Envelope], unwrapped: Vec<Option<String>>) -> Self {
+    let mut keys = unwrapped.into_iter();
+    let mut request = AesRequest {
+      addressed: Vec::with_capacity(envelopes.len()),
+      encrypted: vec![],
+      aes_keys:  vec![],
+    };
+    for envelope in envelopes {
+      let unwrapped: Vec<Option<String>> = keys.by_ref().take(envelope.keys.len()).collect();
+      match unwrapped.into_iter().flatten().next() {
+        Some(key) => {
+          let key = Base64::from(key);
+          if !request.aes_keys.contains(&key) {
+            request.aes_keys.push(key);
+          }
+          request
+            .encrypted
+            .push((envelope.iv.clone(), envelope.ciphertext.clone()));
+          request.addressed.push(true);
+        }
+        None => request.addressed.push(false),
+      }
+    }
+    request
+  }
+}
+
+/// Encrypt a message and append it to the messages in the given data.json
+/// The file still needs to be signed and published afterwards
+pub async fn send<P: IntoInnerPath>(
+  inner_path: P,
+  text: &str,
+  recipients: &[PublicKey],
+) -> Result<Envelope, Error> {
+  let inner_path = inner_path.into_inner_path()?;
+  let envelope = encrypt(text, recipients).await?;
+
+  let mut data: Value = match file_get_string(&inner_path, false, None).await? {
+    Some(content) => serde_json::from_str(&content)?,
+    None => Value::Object(Default::default()),
+  };
+  let data_object = data.as_object_mut().ok_or(Error::InvalidResponse)?;
+  let messages = data_object
+    .entry(MESSAGES_KEY)
+    .or_insert_with(|| Value::Array(vec![]));
+  match messages.as_array_mut() {
+    Some(messages) => messages.push(serde_json::to_value(&envelope)?),
+    None => return Err(Error::InvalidResponse),
+  }
+
+  file_write_string(inner_path, serde_json::to_string_pretty(&data)?).await?;
+  Ok(envelope)
+}
+
+/// Read and decrypt all messages addressed to the user from the matching data.json files
+/// For example: `inbox("data/users/*/data.json", 0)`
+pub async fn inbox<S: Into<String>>(
+  inner_path_pattern: S,
+  privatekey_index: usize,
+) -> Result<Vec<Message>, Error> {
+  let stored =
+    file_query::<Value>(inner_path_pattern.into(), Some(MESSAGES_KEY.to_string())).await?;
+  // Anyone can write malformed entries to their data.json, skip them instead of failing
+  let (inner_paths, envelopes): (Vec<String>, Vec<Envelope>) = stored
+    .into_iter()
+    .filter_map(|stored| serde_json::from_value::<StoredEnvelope>(stored).ok())
+    .map(|stored| (stored.inner_path, stored.envelope))
+    .unzip();
+  let texts = decrypt_multiple(&envelopes, privatekey_index).await?;
+
+  let mut messages: Vec<Message> = inner_paths
+    .into_iter()
+    .zip(envelopes)
+    .zip(texts)
+    .filter_map(|((inner_path, envelope), text)| {
+      text.map(|text| Message {
+        inner_path,
+        date_added: envelope.date_added,
+        text,
+      })
+    })
+    .collect();
+  messages.sort_by_key(|message| message.date_added);
+  Ok(messages)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn encrypted_envelope_decrypts_with_its_key() {
+    // aesEncrypt replies [key, iv, encrypted]
+    let encrypted: AesEncrypted = serde_json::from_str(r#"["a2V5", "aXY=", "Y2lwaGVy"]"#).unwrap();
+    let key = encrypted.key.clone();
+    let envelope = Envelope::new(
+      encrypted,
+      vec![
+        Base64::from("d3JhcHBlZDE=".to_string()),
+        Base64::from("d3JhcHBlZDI=".to_string()),
+      ],
+      1,
+    );
+    let other = Envelope {
+      keys: vec![Base64::from("b3RoZXI=".to_string())],
+      ..envelope.clone()
+    };
+    let envelope: Envelope =
+      serde_json::from_value(serde_json::to_value(&envelope).unwrap()).unwrap();
+
+    // Only the second wrapped key of the first envelope was addressed to the user
+    let unwrapped = vec![None, Some(key.as_str().to_string()), None];
+    let request = AesRequest::new(&[envelope, other], unwrapped);
+    assert_eq!(
+      request,
+      AesRequest {
+        addressed: vec![true, false],
+        encrypted: vec![(
+          Base64::from("aXY=".to_string()),
+          Base64::from("Y2lwaGVy".to_string())
+        )],
+        aes_keys:  vec![key],
+      }
+    );
+  }
+}
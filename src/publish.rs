@@ -0,0 +1,231 @@
+use super::cmdp;
+use crate::content::{content_json_get, ContentJson};
+use crate::events::site_info_stream;
+use crate::inner_path::{InnerPath, IntoInnerPath};
+use crate::responses::{SiteInfo, ZeroResponse};
+use crate::ui_server::{site_info, site_sign};
+use crate::ZeroFrameError as Error;
+use futures::channel::mpsc::UnboundedReceiver;
+use futures::future::{self, Either, LocalBoxFuture};
+use futures::stream::{self, Stream, StreamExt};
+use futures::FutureExt;
+use wasm_bindgen::JsValue;
+
+/// How publishing ended
+#[derive(Clone, Debug, PartialEq)]
+pub enum PublishStatus {
+  /// At least one peer received the content
+  Published,
+  /// There were no peers to publish to, the content is ready locally
+  NoPeers,
+  /// ZeroNet could not publish to any of the peers, with its error message
+  Failed(String),
+}
+
+/// The result of a publish
+/// ZeroNet only reports whether any peer received the content, not how many did.
+#[derive(Clone, Debug)]
+pub struct PublishOutcome {
+  pub inner_path: InnerPath,
+  pub status:     PublishStatus,
+  /// Connected peers of the site when publishing finished, not the number published to
+  pub site_peers: u64,
+  /// Signing did not change the file list, only known when Publish signs
+  pub unchanged:  bool,
+}
+
+impl PublishOutcome {
+  pub fn is_published(&self) -> bool {
+    self.status == PublishStatus::Published
+  }
+}
+
+/// Progress of a publish, the stream ends with Done
+#[derive(Clone, Debug)]
+pub enum PublishProgress {
+  Signed {
+    unchanged: bool,
+  },
+  /// Publishing started, with the site's peer count
+  Publishing {
+    peers: u64,
+  },
+  /// The site's peer count changed while publishing
+  PeersChanged(u64),
+  Done(PublishOutcome),
+}
+
+/// Sign and publish a content.json
+///
+/// ```ignore
+/// let outcome = Publish::new("data/users/1A.../content.json")?.run().await?;
+/// if outcome.status == PublishStatus::NoPeers { ... }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Publish {
+  inner_path:              InnerPath,
+  privatekey:              Option<String>,
+  sign:                    bool,
+  remove_missing_optional: bool,
+}
+
+enum PublishStep {
+  Sign,
+  Publish,
+  Wait(LocalBoxFuture<'static, JsValue>),
+  Finished,
+}
+
+struct PublishState {
+  publish:   Publish,
+  step:      PublishStep,
+  updates:   UnboundedReceiver<SiteInfo>,
+  peers:     u64,
+  unchanged: bool,
+}
+
+/// Sign and publish a content.json with the stored private key, see Publish
+pub async fn sign_publish<P: IntoInnerPath>(inner_path: P) -> Result<PublishOutcome, Error> {
+  Publish::new(inner_path)?.run().await
+}
+
+impl Publish {
+  pub fn new<P: IntoInnerPath>(inner_path: P) -> Result<Self, Error> {
+    Ok(Publish {
+      inner_path:              inner_path.into_inner_path()?,
+      privatekey:              None,
+      sign:                    true,
+      remove_missing_optional: false,
+    })
+  }
+
+  /// Sign with this key instead of the stored one
+  pub fn privatekey<S: Into<String>>(mut self, privatekey: S) -> Self {
+    self.privatekey = Some(privatekey.into());
+    self
+  }
+
+  /// Sign before publishing, enabled by default
+  pub fn sign(mut self, sign: bool) -> Self {
+    self.sign = sign;
+    self
+  }
+
+  pub fn remove_missing_optional(mut self, remove_missing_optional: bool) -> Self {
+    self.remove_missing_optional = remove_missing_optional;
+    self
+  }
+
+  /// Publish and return the outcome, signing errors are returned as Err
+  pub async fn run(self) -> Result<PublishOutcome, Error> {
+    let progress = self.stream();
+    futures::pin_mut!(progress);
+    while let Some(progress) = progress.next().await {
+      if let PublishProgress::Done(outcome) = progress? {
+        return Ok(outcome);
+      }
+    }
+    Err(Error::InvalidResponse)
+  }
+
+  /// Publish and report progress, peer counts come from setSiteInfo updates
+  pub fn stream(self) -> impl Stream<Item = Result<PublishProgress, Error>> {
+    let state = PublishState {
+      step:      match self.sign {
+        true => PublishStep::Sign,
+        false => PublishStep::Publish,
+      },
+      publish:   self,
+      updates:   site_info_stream(),
+      peers:     0,
+      unchanged: false,
+    };
+    stream::unfold(state, |mut state| async move {
+      let progress = state.next().await;
+      if progress.is_err() {
+        state.step = PublishStep::Finished;
+      }
+      progress.transpose().map(|progress| (progress, state))
+    })
+  }
+}
+
+impl PublishState {
+  async fn next(&mut self) -> Result<Option<PublishProgress>, Error> {
+    match std::mem::replace(&mut self.step, PublishStep::Finished) {
+      PublishStep::Sign => {
+        let before = content_json_get(&self.publish.inner_path).await.ok();
+        site_sign(
+          self.publish.privatekey.clone(),
+          Some(self.publish.inner_path.clone()),
+          self.publish.remove_missing_optional,
+        )
+        .await?;
+        let after = content_json_get(&self.publish.inner_path).await?;
+        self.unchanged = match before {
+          Some(before) => same_files(&before, &after),
+          None => false,
+        };
+        self.step = PublishStep::Publish;
+        Ok(Some(PublishProgress::Signed {
+          unchanged: self.unchanged,
+        }))
+      }
+      PublishStep::Publish => {
+        self.peers = site_info().await?.peers;
+        let params = vec![
+          self
+            .publish
+            .privatekey
+            .as_ref()
+            .map(|key| JsValue::from_str(key))
+            .unwrap_or(JsValue::null()),
+          JsValue::from_str(self.publish.inner_path.as_str()),
+          JsValue::from_bool(false),
+        ];
+        self.step = PublishStep::Wait(cmdp("sitePublish", params).boxed_local());
+        Ok(Some(PublishProgress::Publishing { peers: self.peers }))
+      }
+      PublishStep::Wait(mut response) => loop {
+        let update = match future::select(response, self.updates.next()).await {
+          Either::Left((response, _)) => Err(response),
+          Either::Right((site_info, pending)) => Ok((site_info, pending)),
+        };
+        match update {
+          Ok((Some(site_info), pending)) => {
+            response = pending;
+            if site_info.peers != self.peers {
+              self.peers = site_info.peers;
+              self.step = PublishStep::Wait(response);
+              return Ok(Some(PublishProgress::PeersChanged(self.peers)));
+            }
+          }
+          Ok((None, pending)) => {
+            return Ok(Some(PublishProgress::Done(self.outcome(pending.await))))
+          }
+          Err(response) => return Ok(Some(PublishProgress::Done(self.outcome(response)))),
+        }
+      },
+      PublishStep::Finished => Ok(None),
+    }
+  }
+
+  fn outcome(&self, response: JsValue) -> PublishOutcome {
+    let status = match response.result() {
+      Ok(_) if self.peers == 0 => PublishStatus::NoPeers,
+      Ok(_) => PublishStatus::Published,
+      Err(Error::RemoteError(error)) => PublishStatus::Failed(error),
+      Err(err) => PublishStatus::Failed(err.to_string()),
+    };
+    PublishOutcome {
+      inner_path: self.publish.inner_path.clone(),
+      status,
+      site_peers: self.peers,
+      unchanged: self.unchanged,
+    }
+  }
+}
+
+fn same_files(before: &ContentJson, after: &ContentJson) -> bool {
+  before.files == after.files && before.files_optional == after.files_optional
+}
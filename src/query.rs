@@ -0,0 +1,367 @@
+use crate::ZeroFrameError as Error;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// Named parameters for db_query
+///
+/// Values can be anything serializable: numbers, strings, null and lists for `IN :ids`.
+/// ZeroNet also expands `WHERE ?` into `key = :key AND ...` for all of the params.
+///
+/// ```ignore
+/// let params = QueryParams::new()
+///   .set("ids", vec![1, 2, 3])?
+///   .set("directory", "users/1abc")?;
+/// db_query::<Post>("SELECT * FROM post WHERE post_id IN :ids".into(), params).await?;
+/// ```
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+#[serde(transparent)]
+pub struct QueryParams(Map<String, Value>);
+
+impl QueryParams {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Add a parameter, fails if the value can not be serialized
+  pub fn set<K: Into<String>, V: Serialize>(mut self, key: K, value: V) -> Result<Self, Error> {
+    self.insert(key, value)?;
+    Ok(self)
+  }
+
+  pub fn insert<K: Into<String>, V: Serialize>(&mut self, key: K, value: V) -> Result<(), Error> {
+    self.0.insert(key.into(), serde_json::to_value(value)?);
+    Ok(())
+  }
+
+  pub fn get(&self, key: &str) -> Option<&Value> {
+    self.0.get(key)
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (&String, &Value)> {
+    self.0.iter()
+  }
+}
+
+/// ZeroNet stores queries without parameters with an empty list
+impl<'de> Deserialize<'de> for QueryParams {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    match Value::deserialize(deserializer)? {
+      Value::Object(params) => Ok(QueryParams(params)),
+      _ => Ok(QueryParams::default()),
+    }
+  }
+}
+
+impl From<HashMap<String, String>> for QueryParams {
+  fn from(params: HashMap<String, String>) -> Self {
+    QueryParams(
+      params
+        .into_iter()
+        .map(|(key, value)| (key, Value::String(value)))
+        .collect(),
+    )
+  }
+}
+
+impl From<HashMap<String, Value>> for QueryParams {
+  fn from(params: HashMap<String, Value>) -> Self {
+    QueryParams(params.into_iter().collect())
+  }
+}
+
+impl From<Map<String, Value>> for QueryParams {
+  fn from(params: Map<String, Value>) -> Self {
+    QueryParams(params)
+  }
+}
+
+/// Alias of the json table row of a data file in queries built with Query::join_user
+pub const DATA_JSON: &str = "data_json";
+/// Alias of the json table row of the user's content.json in queries built with Query::join_user
+pub const CONTENT_JSON: &str = "content_json";
+
+/// Prefix of the parameters generated by Query, with_param rejects names starting with it
+pub const GENERATED_PARAM_PREFIX: &str = "_query_";
+
+/// Quote an identifier for SQLite, qualified names are quoted per part
+pub fn escape_identifier(identifier: &str) -> String {
+  identifier
+    .split('.')
+    .map(|part| format!("\"{}\"", part.replace('"', "\"\"")))
+    .collect::<Vec<String>>()
+    .join(".")
+}
+
+/// Builder for SELECT queries on ZeroNet site databases
+///
+/// ```ignore
+/// let (sql, params) = Query::from_table("post")
+///   .columns(&["post.title", "post.body"])
+///   .join_user()
+///   .column_as("content_json.cert_user_id", "cert_user_id")
+///   .where_eq("post.topic_id", 5)?
+///   .order_by("post.date_added", true)
+///   .limit(20)
+///   .build();
+/// let posts: Vec<Post> = db_query(sql, params).await?;
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Query {
+  table:            String,
+  columns:          Vec<String>,
+  joins:            Vec<String>,
+  conditions:       Vec<String>,
+  group_by:         Vec<String>,
+  order_by:         Vec<String>,
+  limit:            Option<usize>,
+  offset:           Option<usize>,
+  params:           QueryParams,
+  generated_params: usize,
+}
+
+impl Query {
+  pub fn from_table(table: &str) -> Self {
+    Query {
+      table: escape_identifier(table),
+      ..Default::default()
+    }
+  }
+
+  /// Select columns, without any columns all columns are selected
+  pub fn columns(mut self, columns: &[&str]) -> Self {
+    self
+      .columns
+      .extend(columns.iter().map(|column| escape_identifier(column)));
+    self
+  }
+
+  pub fn column_as(mut self, column: &str, alias: &str) -> Self {
+    self.columns.push(format!(
+      "{} AS {}",
+      escape_identifier(column),
+      escape_identifier(alias)
+    ));
+    self
+  }
+
+  /// Select an unescaped SQL expression
+  pub fn expression_as(mut self, expression: &str, alias: &str) -> Self {
+    self
+      .columns
+      .push(format!("{} AS {}", expression, escape_identifier(alias)));
+    self
+  }
+
+  /// Join the json table on json_id as DATA_JSON, giving access to its directory and file_name
+  /// In merger site databases it also holds the merged site's address in `site`
+  pub fn join_json(mut self) -> Self {
+    self.joins.push(format!(
+      "LEFT JOIN json AS {} ON ({}.json_id = {}.json_id)",
+      DATA_JSON, DATA_JSON, self.table
+    ));
+    self
+  }
+
+  /// Join the json table as DATA_JSON and the user's content.json as CONTENT_JSON
+  /// The user's cert_user_id is then available as content_json.cert_user_id
+  pub fn join_user(self) -> Self {
+    let mut query = self.join_json();
+    query.joins.push(format!(
+      "LEFT JOIN json AS {} ON ({}.directory = {}.directory AND {}.file_name = 'content.json')",
+      CONTENT_JSON, CONTENT_JSON, DATA_JSON, CONTENT_JSON
+    ));
+    query
+  }
+
+  /// Add an unescaped JOIN clause
+  pub fn join_raw(mut self, join: &str) -> Self {
+    self.joins.push(join.to_string());
+    self
+  }
+
+  pub fn where_eq<V: Serialize>(self, column: &str, value: V) -> Result<Self, Error> {
+    self.where_op(column, "=", value)
+  }
+
+  /// Match any of the values, uses ZeroNet's list expansion of `IN :param`
+  pub fn where_in<V: Serialize>(self, column: &str, values: &[V]) -> Result<Self, Error> {
+    self.where_op(column, "IN", values)
+  }
+
+  pub fn where_op<V: Serialize>(mut self, column: &str, op: &str, value: V) -> Result<Self, Error> {
+    let param = self.param(value)?;
+    self
+      .conditions
+      .push(format!("{} {} :{}", escape_identifier(column), op, param));
+    Ok(self)
+  }
+
+  /// Only rows from the given merged site, requires join_json
+  pub fn where_site(self, address: &str) -> Result<Self, Error> {
+    self.where_eq(&format!("{}.site", DATA_JSON), address)
+  }
+
+  /// Only rows from the given user directory, requires join_json
+  pub fn where_directory(self, directory: &str) -> Result<Self, Error> {
+    self.where_eq(&format!("{}.directory", DATA_JSON), directory)
+  }
+
+  /// Add an unescaped condition, use :name placeholders together with with_param
+  pub fn where_raw(mut self, condition: &str) -> Self {
+    self.conditions.push(format!("({})", condition));
+    self
+  }
+
+  /// Bind a parameter used in where_raw or join_raw
+  pub fn with_param<K: Into<String>, V: Serialize>(
+    mut self,
+    key: K,
+    value: V,
+  ) -> Result<Self, Error> {
+    let key = key.into();
+    if key.starts_with(GENERATED_PARAM_PREFIX) {
+      return Err(Error::ReservedQueryParam(key));
+    }
+    self.params.insert(key, value)?;
+    Ok(self)
+  }
+
+  pub fn group_by(mut self, column: &str) -> Self {
+    self.group_by.push(escape_identifier(column));
+    self
+  }
+
+  pub fn order_by(mut self, column: &str, descending: bool) -> Self {
+    let direction = if descending { "DESC" } else { "ASC" };
+    self
+      .order_by
+      .push(format!("{} {}", escape_identifier(column), direction));
+    self
+  }
+
+  pub fn limit(mut self, limit: usize) -> Self {
+    self.limit = Some(limit);
+    self
+  }
+
+  pub fn offset(mut self, offset: usize) -> Self {
+    self.offset = Some(offset);
+    self
+  }
+
+  fn param<V: Serialize>(&mut self, value: V) -> Result<String, Error> {
+    let name = format!("{}{}", GENERATED_PARAM_PREFIX, self.generated_params);
+    self.generated_params += 1;
+    self.params.insert(name.clone(), value)?;
+    Ok(name)
+  }
+
+  /// The SQL for the query, without its params
+  pub fn sql(&self) -> String {
+    let columns = if self.columns.is_empty() {
+      "*".to_string()
+    } else {
+      self.columns.join(", ")
+    };
+    let mut sql = format!("SELECT {} FROM {}", columns, self.table);
+    for join in self.joins.iter() {
+      sql.push(' ');
+      sql.push_str(join);
+    }
+    if !self.conditions.is_empty() {
+      sql.push_str(" WHERE ");
+      sql.push_str(&self.conditions.join(" AND "));
+    }
+    if !self.group_by.is_empty() {
+      sql.push_str(" GROUP BY ");
+      sql.push_str(&self.group_by.join(", "));
+    }
+    if !self.order_by.is_empty() {
+      sql.push_str(" ORDER BY ");
+      sql.push_str(&self.order_by.join(", "));
+    }
+    if let Some(limit) = self.limit {
+      sql.push_str(&format!(" LIMIT {}", limit));
+    }
+    if let Some(offset) = self.offset {
+      sql.push_str(&format!(" OFFSET {}", offset));
+    }
+    sql
+  }
+
+  pub fn params(&self) -> &QueryParams {
+    &self.params
+  }
+
+  /// The SQL and params, ready for db_query
+  pub fn build(self) -> (String, QueryParams) {
+    (self.sql(), self.params)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn escape_identifier_quotes_each_part() {
+    assert_eq!(escape_identifier("topic"), "\"topic\"");
+    assert_eq!(escape_identifier("topic.title"), "\"topic\".\"title\"");
+    assert_eq!(escape_identifier("a\"b"), "\"a\"\"b\"");
+  }
+
+  #[test]
+  fn sql_without_clauses_selects_all() {
+    assert_eq!(Query::from_table("topic").sql(), "SELECT * FROM \"topic\"");
+  }
+
+  #[test]
+  fn sql_with_all_clauses() {
+    let query = Query::from_table("post")
+      .columns(&["post.title"])
+      .column_as("content_json.cert_user_id", "cert_user_id")
+      .join_user()
+      .where_eq("post.topic_id", 5)
+      .unwrap()
+      .where_in("post.post_id", &[1, 2])
+      .unwrap()
+      .group_by("post.topic_id")
+      .order_by("post.date_added", true)
+      .limit(10)
+      .offset(20);
+    assert_eq!(
+      query.sql(),
+      "SELECT \"post\".\"title\", \"content_json\".\"cert_user_id\" AS \"cert_user_id\" \
+       FROM \"post\" \
+       LEFT JOIN json AS data_json ON (data_json.json_id = \"post\".json_id) \
+       LEFT JOIN json AS content_json ON (content_json.directory = data_json.directory \
+       AND content_json.file_name = 'content.json') \
+       WHERE \"post\".\"topic_id\" = :_query_0 AND \"post\".\"post_id\" IN :_query_1 \
+       GROUP BY \"post\".\"topic_id\" ORDER BY \"post\".\"date_added\" DESC LIMIT 10 OFFSET 20"
+    );
+    assert_eq!(query.params().get("_query_0"), Some(&json!(5)));
+    assert_eq!(query.params().get("_query_1"), Some(&json!([1, 2])));
+  }
+
+  #[test]
+  fn generated_params_do_not_overwrite_user_params() {
+    let query = Query::from_table("topic")
+      .with_param("p1", "raw")
+      .unwrap()
+      .where_eq("topic_id", 1)
+      .unwrap()
+      .where_eq("title", "title")
+      .unwrap();
+    assert_eq!(query.params().get("p1"), Some(&json!("raw")));
+    assert_eq!(query.params().iter().count(), 3);
+    assert!(Query::from_table("topic")
+      .with_param("_query_0", 1)
+      .is_err());
+  }
+}
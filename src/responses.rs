@@ -1,4 +1,6 @@
+use crate::address::{AuthAddress, CertUserId, SiteAddress};
 use crate::error::ZeroFrameError as Error;
+use crate::wrapper::Permission;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
@@ -6,20 +8,26 @@ use wasm_bindgen::JsValue;
 
 pub trait ZeroResponse {
   fn result(self) -> Result<(), Error>;
+  fn result_message(self, message: &str) -> Result<(), Error>;
   fn result_changed(self) -> Result<bool, Error>;
   fn response<T: DeserializeOwned>(self) -> Result<T, Error>;
 }
 
 impl ZeroResponse for JsValue {
   fn result(self) -> Result<(), Error> {
+    self.result_message("ok")
+  }
+  fn result_message(self, message: &str) -> Result<(), Error> {
     if let Some(result) = self.as_string() {
-      if result == "ok".to_string() {
+      if result == message {
         return Ok(());
       } else if self.is_falsy() {
         return Err(Error::FalsyResponse);
       } else if let Some(err) = Error::from_response(result) {
         return Err(err);
       }
+    } else if let Ok(response) = self.into_serde::<ErrorResponse>() {
+      return Err(Error::RemoteError(response.error));
     }
     Err(Error::InvalidResponse)
   }
@@ -61,14 +69,17 @@ pub struct PeerLocation {
   pub country: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct FileRules {
-  pub current_size:  usize,
-  pub cert_signers:  HashMap<String, Vec<String>>,
-  pub files_allowed: String,
-  pub signers:       Vec<String>,
-  pub user_address:  String,
-  pub max_size:      usize,
+  pub current_size:           usize,
+  pub cert_signers:           HashMap<String, Vec<String>>,
+  pub files_allowed:          String,
+  /// Only set for user content with a separate pattern for optional files
+  pub files_allowed_optional: Option<String>,
+  pub signers:                Vec<String>,
+  pub user_address:           Option<AuthAddress>,
+  pub max_size:               usize,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -104,7 +115,7 @@ pub struct ServerInfo {
   pub version:         String,
 }
 
-#[derive(Serialize, Deserialize, Default)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 #[serde(default)]
 pub struct SiteSettings {
   pub added:               u64,
@@ -124,7 +135,25 @@ pub struct SiteSettings {
   pub size_optional:       u64,
 }
 
-#[derive(Serialize, Deserialize, Default)]
+impl SiteSettings {
+  pub fn has_permission(&self, permission: &Permission) -> bool {
+    self
+      .permissions
+      .iter()
+      .any(|granted| granted == &permission.to_string())
+  }
+
+  /// The given permissions that have not been granted yet
+  pub fn missing_permissions(&self, permissions: &[Permission]) -> Vec<Permission> {
+    permissions
+      .iter()
+      .filter(|permission| !self.has_permission(permission))
+      .cloned()
+      .collect()
+  }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 #[serde(default)]
 pub struct SiteContentSummary {
   pub address: String,
@@ -150,16 +179,16 @@ pub struct SiteContentSummary {
   pub zeronet_version: String,
 }
 
-#[derive(Serialize, Deserialize, Default)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 #[serde(default)]
 pub struct SiteInfo {
-  pub address:          String,
+  pub address:          Option<SiteAddress>,
   pub address_hash:     String,
   pub address_short:    String,
   pub tasks:            u64,
   pub size_limit:       u64,
   pub next_size_limit:  u64,
-  pub auth_address:     Option<String>,
+  pub auth_address:     Option<AuthAddress>,
   pub auth_key_sha512:  String,
   pub peers:            u64,
   pub auth_key:         String,
@@ -167,7 +196,322 @@ pub struct SiteInfo {
   pub bad_files:        u64,
   pub workers:          u64,
   pub content:          SiteContentSummary,
-  pub cert_user_id:     Option<String>,
+  pub cert_user_id:     Option<CertUserId>,
   pub started_task_num: u64,
+  pub event:            Option<SiteEvent>,
   // pub content_updated: Option<bool>,
 }
+
+/// Event attached to a setSiteInfo update, sent by ZeroNet as [name, value]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(from = "(String, Value)", into = "(String, Value)")]
+pub enum SiteEvent {
+  FileStarted(String),
+  FileDone(String),
+  FileFailed(String),
+  PeersAdded(u64),
+  CertChanged(Option<String>),
+  Other(String, Value),
+}
+
+impl From<(String, Value)> for SiteEvent {
+  fn from((name, value): (String, Value)) -> Self {
+    match (name.as_str(), &value) {
+      ("file_started", Value::String(inner_path)) => SiteEvent::FileStarted(inner_path.clone()),
+      ("file_done", Value::String(inner_path)) => SiteEvent::FileDone(inner_path.clone()),
+      ("file_failed", Value::String(inner_path)) => SiteEvent::FileFailed(inner_path.clone()),
+      ("peers_added", Value::Number(peers)) if peers.is_u64() => {
+        SiteEvent::PeersAdded(peers.as_u64().unwrap_or_default())
+      }
+      ("cert_changed", Value::String(domain)) => SiteEvent::CertChanged(Some(domain.clone())),
+      ("cert_changed", Value::Null) => SiteEvent::CertChanged(None),
+      _ => SiteEvent::Other(name, value),
+    }
+  }
+}
+
+impl From<SiteEvent> for (String, Value) {
+  fn from(event: SiteEvent) -> Self {
+    match event {
+      SiteEvent::FileStarted(inner_path) => ("file_started".to_string(), Value::from(inner_path)),
+      SiteEvent::FileDone(inner_path) => ("file_done".to_string(), Value::from(inner_path)),
+      SiteEvent::FileFailed(inner_path) => ("file_failed".to_string(), Value::from(inner_path)),
+      SiteEvent::PeersAdded(peers) => ("peers_added".to_string(), Value::from(peers)),
+      SiteEvent::CertChanged(domain) => (
+        "cert_changed".to_string(),
+        domain.map(Value::from).unwrap_or(Value::Null),
+      ),
+      SiteEvent::Other(name, value) => (name, value),
+    }
+  }
+}
+
+/// Base64 encoded data as exchanged with the CryptMessage plugin
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(transparent)]
+pub struct Base64(String);
+
+impl Base64 {
+  pub fn encode<T: AsRef<[u8]>>(data: T) -> Self {
+    Base64(base64::encode(data))
+  }
+
+  pub fn decode(&self) -> Result<Vec<u8>, Error> {
+    Ok(base64::decode(&self.0)?)
+  }
+
+  pub fn as_str(&self) -> &str {
+    &self.0
+  }
+}
+
+impl From<String> for Base64 {
+  fn from(encoded: String) -> Self {
+    Base64(encoded)
+  }
+}
+
+impl From<Base64> for String {
+  fn from(encoded: Base64) -> Self {
+    encoded.0
+  }
+}
+
+/// A user's base64 encoded secp256k1 public key
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(transparent)]
+pub struct PublicKey(String);
+
+impl PublicKey {
+  pub fn as_str(&self) -> &str {
+    &self.0
+  }
+}
+
+impl From<String> for PublicKey {
+  fn from(publickey: String) -> Self {
+    PublicKey(publickey)
+  }
+}
+
+impl From<PublicKey> for String {
+  fn from(publickey: PublicKey) -> Self {
+    publickey.0
+  }
+}
+
+/// Result of aesEncrypt, returned as [key, iv, encrypted] by ZeroNet
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(from = "(Base64, Base64, Base64)")]
+pub struct AesEncrypted {
+  pub key:        Base64,
+  pub iv:         Base64,
+  pub ciphertext: Base64,
+}
+
+impl From<(Base64, Base64, Base64)> for AesEncrypted {
+  fn from((key, iv, ciphertext): (Base64, Base64, Base64)) -> Self {
+    AesEncrypted {
+      key,
+      iv,
+      ciphertext,
+    }
+  }
+}
+
+/// eciesEncrypt replies [encrypted, aes_key] if the key was requested
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EciesEncryptedResponse {
+  WithKey(Base64, Base64),
+  Plain(Base64),
+}
+
+/// Result of eciesEncrypt, the AES key is only present if it was requested
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(from = "EciesEncryptedResponse")]
+pub struct EciesEncrypted {
+  pub aes_key:    Option<Base64>,
+  pub ciphertext: Base64,
+}
+
+impl From<EciesEncryptedResponse> for EciesEncrypted {
+  fn from(response: EciesEncryptedResponse) -> Self {
+    match response {
+      EciesEncryptedResponse::WithKey(ciphertext, aes_key) => EciesEncrypted {
+        aes_key: Some(aes_key),
+        ciphertext,
+      },
+      EciesEncryptedResponse::Plain(ciphertext) => EciesEncrypted {
+        aes_key: None,
+        ciphertext,
+      },
+    }
+  }
+}
+
+/// A row of the user's newsfeed, sites may select NULL for any of the columns
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct FeedItem {
+  #[serde(rename = "type")]
+  pub item_type:  Option<String>,
+  pub title:      Option<String>,
+  pub body:       Option<String>,
+  pub url:        Option<String>,
+  pub date_added: f64,
+  pub site:       String,
+  pub feed_name:  String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct FeedStat {
+  pub site:      String,
+  pub feed_name: String,
+  pub taken:     f64,
+}
+
+/// Result of feedQuery and feedSearch
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct FeedQueryResult {
+  pub rows:  Vec<FeedItem>,
+  pub stats: Vec<FeedStat>,
+  pub num:   usize,
+  pub sites: usize,
+  pub taken: f64,
+}
+
+/// A merged site together with the merged type from its content.json
+#[derive(Serialize, Deserialize, Default)]
+#[serde(from = "SiteInfo")]
+pub struct MergedSite {
+  pub merged_type: String,
+  #[serde(flatten)]
+  pub site_info:   SiteInfo,
+}
+
+impl From<SiteInfo> for MergedSite {
+  fn from(site_info: SiteInfo) -> Self {
+    MergedSite {
+      merged_type: site_info.content.merged_type.clone(),
+      site_info,
+    }
+  }
+}
+
+/// Result of serverPortcheck, None if the port was not checked for the IP version
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct PortCheck {
+  pub ipv4: Option<bool>,
+  pub ipv6: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ConfigEntry {
+  pub value:   Value,
+  pub default: Value,
+  pub pending: bool,
+}
+
+/// Result of configList, keyed by config name
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(transparent)]
+pub struct ServerConfig(pub HashMap<String, ConfigEntry>);
+
+impl ServerConfig {
+  pub fn get<K: ToString>(&self, key: K) -> Option<&ConfigEntry> {
+    self.0.get(&key.to_string())
+  }
+}
+
+/// A muted user, as returned by muteList
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MuteEntry {
+  pub cert_user_id: CertUserId,
+  #[serde(default)]
+  pub reason:       String,
+  #[serde(default)]
+  pub source:       String,
+  #[serde(default)]
+  pub date_added:   f64,
+}
+
+/// A certificate of the user, as returned by certList
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct CertEntry {
+  pub auth_address:   String,
+  pub auth_type:      String,
+  pub auth_user_name: String,
+  pub domain:         String,
+  pub selected:       bool,
+}
+
+/// Well-known keys of the user's global settings, other keys are kept in `other`
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct GlobalSettings {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub theme:            Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub use_system_theme: Option<bool>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub language:         Option<String>,
+  #[serde(flatten)]
+  pub other:            HashMap<String, Value>,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn site_info_with_missing_fields() {
+    let site_info: SiteInfo = serde_json::from_str(
+      r#"{"address": "1HeLLo4uzjaLetFx6NH3PMwFP3qbRbTf3D", "peers": 3,
+          "cert_user_id": "user@zeroid.bit", "content": {}, "settings": {}}"#,
+    )
+    .unwrap();
+    assert_eq!(site_info.peers, 3);
+    assert!(site_info.auth_address.is_none());
+    assert_eq!(site_info.cert_user_id.unwrap().domain(), "zeroid.bit");
+  }
+
+  #[test]
+  fn aes_encrypted_from_key_iv_encrypted() {
+    let encrypted: AesEncrypted = serde_json::from_str(r#"["a2V5", "aXY=", "ZGF0YQ=="]"#).unwrap();
+    assert_eq!(encrypted.key.as_str(), "a2V5");
+    assert_eq!(encrypted.iv.as_str(), "aXY=");
+    assert_eq!(encrypted.ciphertext.as_str(), "ZGF0YQ==");
+  }
+
+  #[test]
+  fn ecies_encrypted_with_aes_key() {
+    let encrypted: EciesEncrypted = serde_json::from_str(r#"["ZGF0YQ==", "a2V5"]"#).unwrap();
+    assert_eq!(encrypted.ciphertext.as_str(), "ZGF0YQ==");
+    assert_eq!(encrypted.aes_key.unwrap().as_str(), "a2V5");
+  }
+
+  #[test]
+  fn feed_item_with_float_date_and_null_columns() {
+    let item: FeedItem = serde_json::from_str(
+      r#"{"type": "post", "title": null, "body": "body", "url": null,
+          "date_added": 1600000000.5, "site": "1BLog", "feed_name": "Posts"}"#,
+    )
+    .unwrap();
+    assert_eq!(item.date_added, 1600000000.5);
+    assert!(item.title.is_none());
+    assert!(item.url.is_none());
+  }
+
+  #[test]
+  fn ecies_encrypted_without_aes_key() {
+    let encrypted: EciesEncrypted = serde_json::from_str(r#""ZGF0YQ==""#).unwrap();
+    assert_eq!(encrypted.ciphertext.as_str(), "ZGF0YQ==");
+    assert!(encrypted.aes_key.is_none());
+  }
+}
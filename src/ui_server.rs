@@ -1,9 +1,18 @@
-use super::{cmd, cmdp};
+use super::{cmd, cmdp, sleep};
+use crate::address::CertUserId;
+use crate::content::file_rules_check;
 use crate::error::ZeroFrameError as Error;
-use crate::responses::{AnnouncerInfo, FileRules, ServerInfo, SiteInfo, ZeroResponse};
-use futures::FutureExt;
+use crate::events::site_info_stream;
+use crate::inner_path::{InnerPath, IntoInnerPath};
+use crate::query::QueryParams;
+use crate::responses::{
+  AnnouncerInfo, CertEntry, ErrorResponse, FileRules, ServerInfo, SiteInfo, ZeroResponse,
+};
+use crate::wrapper::{permission_add, Permission};
+use futures::future::{self, Either};
+use futures::{pin_mut, select, FutureExt, StreamExt};
 use serde::{de::DeserializeOwned, Serialize};
-use std::collections::HashMap;
+use serde_json::Value;
 use wasm_bindgen::prelude::*;
 
 pub async fn announcer_info() -> Result<AnnouncerInfo, Error> {
@@ -32,105 +41,222 @@ pub async fn cert_add<S: Into<String>>(
   response.result_changed()
 }
 
-pub fn cert_select(accepted_domains: Vec<String>, accept_any: bool, accepted_pattern: String) {
+/// List the certificates of the current user
+pub async fn cert_list() -> Result<Vec<CertEntry>, Error> {
+  let response = cmdp("certList", vec![]).await;
+  response.response::<Vec<CertEntry>>()
+}
+
+/// Use the certificate of the given domain for this site
+pub async fn cert_set<S: Into<String>>(domain: S) -> Result<(), Error> {
+  let response = cmdp("certSet", vec![JsValue::from_str(&domain.into())]).await;
+  response.result()
+}
+
+/// Ask the user to select a certificate for this site.
+/// Returns the selected cert_user_id, or Ok(None) if the user chose not to use a certificate.
+/// ZeroNet replies once the user picked an entry, also when it is the already selected one.
+/// It does not report a dismissed dialog, the timeout (in ms) is used to stop waiting for it.
+pub async fn cert_select(
+  accepted_domains: Vec<String>,
+  accept_any: bool,
+  accepted_pattern: String,
+  timeout: Option<usize>,
+) -> Result<Option<CertUserId>, Error> {
   let params = vec![
-    JsValue::from_serde(&accepted_domains).unwrap(),
+    JsValue::from_serde(&accepted_domains)?,
     JsValue::from_bool(accept_any),
     JsValue::from_str(&accepted_pattern),
   ];
-  cmd("certSelect", params)
+  let selected = cmdp("certSelect", params);
+  let response = match timeout {
+    Some(timeout) => {
+      match future::select(Box::pin(selected), Box::pin(sleep(timeout as f64))).await {
+        Either::Left((response, _)) => response,
+        Either::Right(_) => return Ok(None),
+      }
+    }
+    None => selected.await,
+  };
+  response.result()?;
+  Ok(site_info().await?.cert_user_id)
 }
 
 pub fn channel_join(channel: String) {
   cmd("channelJoin", vec![JsValue::from_str(&channel)])
 }
 
+/// Run a query on the site's database
+/// Params can be a QueryParams or a HashMap<String, String>
 pub async fn db_query<T: DeserializeOwned>(
   query: String,
-  params: HashMap<String, String>,
+  params: impl Into<QueryParams>,
 ) -> Result<Vec<T>, Error> {
   let response = cmdp(
     "dbQuery",
     vec![
       JsValue::from_str(&query),
-      JsValue::from_serde(&params).unwrap(),
+      JsValue::from_serde(&params.into())?,
     ],
   )
   .await;
   response.response::<Vec<T>>()
 }
 
-pub async fn dir_list<S: Into<String>>(inner_path: S) -> Result<Vec<String>, Error> {
-  let response = cmdp("dirList", vec![JsValue::from_str(&inner_path.into())]).await;
+/// Settings key under which db_rebuild_if_schema_changed stores the schema hash
+pub const DBSCHEMA_HASH_KEY: &str = "dbschema_hash";
+
+/// Close and reopen the site's database, requires the ADMIN permission
+pub async fn db_reload() -> Result<(), Error> {
+  let response = cmdp("dbReload", vec![]).await;
+  response.result()
+}
+
+/// Rebuild the site's database from its json files, requires the ADMIN permission
+pub async fn db_rebuild() -> Result<(), Error> {
+  let response = cmdp("dbRebuild", vec![]).await;
+  response.result()
+}
+
+/// Rebuild the site's database, calling on_progress for every setSiteInfo update until it is done
+pub async fn db_rebuild_with_progress<F: FnMut(&SiteInfo)>(
+  mut on_progress: F,
+) -> Result<(), Error> {
+  let mut updates = site_info_stream().fuse();
+  let rebuild = db_rebuild().fuse();
+  pin_mut!(rebuild);
+  loop {
+    select! {
+      result = rebuild => return result,
+      site_info = updates.next() => {
+        if let Some(site_info) = site_info {
+          on_progress(&site_info);
+        }
+      }
+    }
+  }
+}
+
+/// Rebuild the database if dbschema.json changed since the last call
+/// The hash of the schema is kept in the user's site settings under DBSCHEMA_HASH_KEY,
+/// the first call only stores it.
+/// Returns Ok(true) if the database was rebuilt, requires the ADMIN permission
+pub async fn db_rebuild_if_schema_changed() -> Result<bool, Error> {
+  let schema = file_get_string("dbschema.json", true, None)
+    .await?
+    .ok_or(Error::FalsyResponse)?;
+  let schema: Value = serde_json::from_str(&schema)?;
+  let hash = format!("{:016x}", fnv1a(serde_json::to_string(&schema)?.as_bytes()));
+
+  let mut settings = match user_get_settings::<Value>().await? {
+    Value::Object(settings) => settings,
+    _ => Default::default(),
+  };
+  let changed = match settings.get(DBSCHEMA_HASH_KEY).and_then(Value::as_str) {
+    Some(stored) if stored == hash => return Ok(false),
+    Some(_) => true,
+    // ZeroNet built the database with the current schema, only remember it
+    None => false,
+  };
+
+  if changed {
+    db_rebuild().await?;
+  }
+  settings.insert(DBSCHEMA_HASH_KEY.to_string(), Value::from(hash));
+  user_set_settings(settings).await?;
+  Ok(changed)
+}
+
+/// Stable 64-bit FNV-1a hash
+fn fnv1a(data: &[u8]) -> u64 {
+  data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+    (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+  })
+}
+
+pub async fn dir_list<P: IntoInnerPath>(inner_path: P) -> Result<Vec<String>, Error> {
+  let inner_path = inner_path.into_inner_path()?;
+  let response = cmdp("dirList", vec![JsValue::from_str(inner_path.as_str())]).await;
   response.response::<Vec<String>>()
 }
 
-pub async fn file_delete<S: Into<String>>(inner_path: S) -> Result<(), Error> {
-  let response = cmdp("fileDelete", vec![JsValue::from_str(&inner_path.into())]).await;
+pub async fn file_delete<P: IntoInnerPath>(inner_path: P) -> Result<(), Error> {
+  let inner_path = inner_path.into_inner_path()?;
+  let response = cmdp("fileDelete", vec![JsValue::from_str(inner_path.as_str())]).await;
   response.result()
 }
 
+/// Ok(None) if the file does not exist, an `{error: ..}` reply is returned as Err
 async fn file_get(
-  inner_path: String,
+  inner_path: InnerPath,
   required: bool,
   format: &str,
   timeout: Option<usize>,
-) -> JsValue {
-  cmdp(
+) -> Result<Option<String>, Error> {
+  let response = cmdp(
     "fileGet",
     vec![
-      JsValue::from_str(&inner_path),
+      JsValue::from_str(inner_path.as_str()),
       JsValue::from_bool(required),
       JsValue::from_str(format),
       JsValue::from_f64(timeout.unwrap_or(0) as f64),
     ],
   )
-  .await
+  .await;
+  if let Some(content) = response.as_string() {
+    return Ok(Some(content));
+  }
+  if response.is_null() || response.is_undefined() {
+    return Ok(None);
+  }
+  match response.into_serde::<ErrorResponse>() {
+    Ok(response) => Err(Error::RemoteError(response.error)),
+    Err(_) => Err(Error::InvalidResponse),
+  }
 }
 
-// TODO: return result
-pub async fn file_get_string<S: Into<String>>(
-  inner_path: S,
+/// Read a text file, returns Ok(None) if it does not exist or could not be downloaded in time
+pub async fn file_get_string<P: IntoInnerPath>(
+  inner_path: P,
   required: bool,
   timeout: Option<usize>,
-) -> Option<String> {
-  file_get(inner_path.into(), required, "text", timeout)
-    .map(|js_value| js_value.as_string())
-    .await
+) -> Result<Option<String>, Error> {
+  let inner_path = inner_path.into_inner_path()?;
+  file_get(inner_path, required, "text", timeout).await
 }
 
-// TODO: return result
-pub async fn file_get_bytes(
-  inner_path: String,
+/// Read a binary file, returns Ok(None) if it does not exist or could not be downloaded in time
+pub async fn file_get_bytes<P: IntoInnerPath>(
+  inner_path: P,
   required: bool,
   timeout: Option<usize>,
-) -> Option<Vec<u8>> {
-  file_get(inner_path, required, "base64", timeout)
-    .map(|js_value| {
-      js_value
-        .as_string()
-        .map(|js_string| base64::decode(&js_string).unwrap())
-    })
-    .await
+) -> Result<Option<Vec<u8>>, Error> {
+  let inner_path = inner_path.into_inner_path()?;
+  match file_get(inner_path, required, "base64", timeout).await? {
+    Some(content_base64) => Ok(Some(base64::decode(&content_base64)?)),
+    None => Ok(None),
+  }
 }
 
 /// Recursively list of files in a directory
-pub async fn file_list(inner_path: String) -> Result<Vec<String>, Error> {
-  let response = cmdp("fileList", vec![JsValue::from_str(&inner_path)]).await;
+pub async fn file_list<P: IntoInnerPath>(inner_path: P) -> Result<Vec<String>, Error> {
+  let inner_path = inner_path.into_inner_path()?;
+  let response = cmdp("fileList", vec![JsValue::from_str(inner_path.as_str())]).await;
   response.response::<Vec<String>>()
 }
 
 /// Initialize download of an (optional) file
 /// This function does nothing unless the future is resolved
 /// This function may result in errors if timeout is 0
-pub async fn file_need(inner_path: String, timeout: usize) -> Result<(), Error> {
+pub async fn file_need<P: IntoInnerPath>(inner_path: P, timeout: usize) -> Result<(), Error> {
   if timeout == 0 {
     return Err(Error::RemoteError("Timeout should not be 0".to_string()));
   }
+  let inner_path = inner_path.into_inner_path()?;
   let response = cmdp(
     "fileNeed",
     vec![
-      JsValue::from_str(&inner_path),
+      JsValue::from_str(inner_path.as_str()),
       JsValue::from_f64(timeout as f64),
     ],
   )
@@ -139,6 +265,7 @@ pub async fn file_need(inner_path: String, timeout: usize) -> Result<(), Error>
 }
 
 /// Simple json file query command
+/// The path may contain wildcards, like data/users/*/data.json
 pub async fn file_query<T: DeserializeOwned>(
   dir_inner_path: String,
   query: Option<String>,
@@ -151,25 +278,54 @@ pub async fn file_query<T: DeserializeOwned>(
   response.response::<Vec<T>>()
 }
 
-pub async fn file_rules(inner_path: String) -> Result<FileRules, Error> {
-  let response = cmdp("fileRules", vec![JsValue::from_str(&inner_path)]).await;
+pub async fn file_rules<P: IntoInnerPath>(inner_path: P) -> Result<FileRules, Error> {
+  let inner_path = inner_path.into_inner_path()?;
+  let response = cmdp("fileRules", vec![JsValue::from_str(inner_path.as_str())]).await;
+  // FileRules defaults every field, an error reply would parse as rules that allow anything
+  if let Ok(response) = response.into_serde::<ErrorResponse>() {
+    return Err(Error::RemoteError(response.error));
+  }
   response.response::<FileRules>()
 }
 
-pub async fn file_write_bytes(inner_path: String, content: Vec<u8>) -> Result<(), Error> {
+pub async fn file_write_bytes<P: IntoInnerPath>(
+  inner_path: P,
+  content: Vec<u8>,
+) -> Result<(), Error> {
   let content_base64 = base64::encode(content);
-  file_write(inner_path, content_base64).await
+  file_write(inner_path.into_inner_path()?, content_base64).await
+}
+
+pub async fn file_write_string<P: IntoInnerPath>(
+  inner_path: P,
+  content: String,
+) -> Result<(), Error> {
+  file_write_bytes(inner_path, content.into_bytes()).await
 }
 
-pub async fn file_write_string(inner_path: String, content: String) -> Result<(), Error> {
-  file_write_bytes(inner_path, content.as_bytes().to_vec()).await
+/// Write a binary file after checking it against the fileRules of its content.json
+pub async fn file_write_bytes_checked<P: IntoInnerPath>(
+  inner_path: P,
+  content: Vec<u8>,
+) -> Result<(), Error> {
+  let inner_path = inner_path.into_inner_path()?;
+  file_rules_check(&inner_path, content.len() as u64).await?;
+  file_write_bytes(inner_path, content).await
 }
 
-async fn file_write(inner_path: String, content_base64: String) -> Result<(), Error> {
+/// Write a text file after checking it against the fileRules of its content.json
+pub async fn file_write_string_checked<P: IntoInnerPath>(
+  inner_path: P,
+  content: String,
+) -> Result<(), Error> {
+  file_write_bytes_checked(inner_path, content.into_bytes()).await
+}
+
+async fn file_write(inner_path: InnerPath, content_base64: String) -> Result<(), Error> {
   let response = cmdp(
     "fileWrite",
     vec![
-      JsValue::from_str(&inner_path),
+      JsValue::from_str(inner_path.as_str()),
       JsValue::from_str(&content_base64),
     ],
   )
@@ -177,6 +333,40 @@ async fn file_write(inner_path: String, content_base64: String) -> Result<(), Er
   response.result()
 }
 
+/// Get a description of what the permission grants
+pub async fn permission_details(permission: &Permission) -> Result<String, Error> {
+  let response = cmdp(
+    "permissionDetails",
+    vec![JsValue::from_str(&permission.to_string())],
+  )
+  .await;
+  response.response::<String>()
+}
+
+/// Revoke a permission of the current site, requires the ADMIN permission
+pub async fn permission_remove(permission: &Permission) -> Result<(), Error> {
+  let response = cmdp(
+    "permissionRemove",
+    vec![JsValue::from_str(&permission.to_string())],
+  )
+  .await;
+  response.result()
+}
+
+/// Returns whether the current site has been granted the permission
+pub async fn has_permission(permission: &Permission) -> Result<bool, Error> {
+  Ok(site_info().await?.settings.has_permission(permission))
+}
+
+/// Ask the user only for the permissions the site does not have yet
+pub async fn permission_request(permissions: &[Permission]) -> Result<(), Error> {
+  let missing = site_info().await?.settings.missing_permissions(permissions);
+  for permission in missing.iter() {
+    permission_add(permission).await?;
+  }
+  Ok(())
+}
+
 /// Returns Ok(()) if ZeroNet returns pong
 pub async fn ping() -> Result<(), Error> {
   let result = cmdp("ping", vec![]).await;
@@ -201,17 +391,18 @@ pub async fn site_info() -> Result<SiteInfo, Error> {
   response.response::<SiteInfo>()
 }
 
-///
+/// Publish a content.json, the root content.json if inner_path is None
 pub async fn site_publish(
   privatekey: Option<String>,
-  inner_path: Option<String>,
+  inner_path: Option<InnerPath>,
   sign: bool,
 ) -> Result<(), Error> {
+  let inner_path = inner_path.unwrap_or_else(root_content_json);
   let response = cmdp(
     "sitePublish",
     vec![
       privatekey.map(|key| JsValue::from_str(&key)).unwrap_or(JsValue::null()),
-      JsValue::from_str(&inner_path.unwrap_or("content.json".to_string())),
+      JsValue::from_str(inner_path.as_str()),
       JsValue::from_bool(sign),
     ],
   )
@@ -224,21 +415,92 @@ pub async fn site_reload() -> Result<(), Error> {
   response.result()
 }
 
+/// Sign a content.json, the root content.json if inner_path is None
 pub async fn site_sign(
   privatekey: Option<String>,
-  inner_path: Option<String>,
+  inner_path: Option<InnerPath>,
   remove_missing_optional: bool,
 ) -> Result<(), Error> {
+  let mut options = SignOptions::new().remove_missing_optional(remove_missing_optional);
+  options.privatekey = privatekey;
+  site_sign_with(inner_path.unwrap_or_else(root_content_json), &options).await
+}
+
+fn root_content_json() -> InnerPath {
+  InnerPath::root().content_json()
+}
+
+/// Options of siteSign, see site_sign_with
+#[derive(Clone, Debug)]
+pub struct SignOptions {
+  privatekey:              Option<String>,
+  remove_missing_optional: bool,
+  update_changed_files:    bool,
+  response_ok:             bool,
+}
+
+impl Default for SignOptions {
+  fn default() -> Self {
+    SignOptions {
+      privatekey:              None,
+      remove_missing_optional: false,
+      update_changed_files:    false,
+      response_ok:             true,
+    }
+  }
+}
+
+impl SignOptions {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Sign with this key instead of the stored one
+  pub fn privatekey<S: Into<String>>(mut self, privatekey: S) -> Self {
+    self.privatekey = Some(privatekey.into());
+    self
+  }
+
+  /// Remove optional files from content.json that no longer exist
+  pub fn remove_missing_optional(mut self, remove_missing_optional: bool) -> Self {
+    self.remove_missing_optional = remove_missing_optional;
+    self
+  }
+
+  /// Also reload the changed files into the database
+  pub fn update_changed_files(mut self, update_changed_files: bool) -> Self {
+    self.update_changed_files = update_changed_files;
+    self
+  }
+
+  /// ZeroNet responds with the signed inner_path instead of "ok" if disabled
+  pub fn response_ok(mut self, response_ok: bool) -> Self {
+    self.response_ok = response_ok;
+    self
+  }
+}
+
+/// Sign a content.json with all of ZeroNet's siteSign options
+pub async fn site_sign_with<P: IntoInnerPath>(
+  inner_path: P,
+  options: &SignOptions,
+) -> Result<(), Error> {
+  let inner_path = inner_path.into_inner_path()?;
   let response = cmdp(
     "siteSign",
     vec![
-      JsValue::from(privatekey.unwrap_or("stored".to_string())),
-      JsValue::from(inner_path.unwrap_or("content.json".to_string())),
-      JsValue::from_bool(remove_missing_optional),
+      JsValue::from(options.privatekey.as_deref().unwrap_or("stored")),
+      JsValue::from_str(inner_path.as_str()),
+      JsValue::from_bool(options.remove_missing_optional),
+      JsValue::from_bool(options.update_changed_files),
+      JsValue::from_bool(options.response_ok),
     ],
   )
   .await;
-  response.result()
+  match options.response_ok {
+    true => response.result(),
+    false => response.result_message(inner_path.as_str()),
+  }
 }
 
 pub fn site_update(address: Option<String>) {
@@ -261,3 +523,17 @@ pub async fn user_set_settings<T: Serialize>(settings: T) -> Result<(), Error> {
   let response = cmdp("userSetSettings", vec![settings]).await;
   response.result()
 }
+
+/// Get the user's global settings, use GlobalSettings for the well-known keys
+pub async fn user_get_global_settings<T: DeserializeOwned>() -> Result<T, Error> {
+  let response = cmdp("userGetGlobalSettings", vec![]).await;
+  response.response::<T>()
+}
+
+/// Set the user's global settings, requires the ADMIN permission
+/// This replaces all global settings, read and modify them first to keep other keys
+pub async fn user_set_global_settings<T: Serialize>(settings: T) -> Result<(), Error> {
+  let settings = JsValue::from_serde(&settings)?;
+  let response = cmdp("userSetGlobalSettings", vec![settings]).await;
+  response.result()
+}
@@ -0,0 +1,198 @@
+use crate::address::AuthAddress;
+use crate::content::{file_rules_check, ContentJson};
+use crate::inner_path::InnerPath;
+use crate::ui_server::{
+  file_delete, file_get_string, file_write_string, site_info, site_publish, site_sign,
+};
+use crate::ZeroFrameError as Error;
+use serde::{de::DeserializeOwned, Serialize};
+use std::fmt;
+use std::marker::PhantomData;
+
+/// The step of a user data update that failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserDataStage {
+  Read,
+  Parse,
+  Serialize,
+  Write,
+  Sign,
+  Publish,
+}
+
+impl fmt::Display for UserDataStage {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let stage = match self {
+      UserDataStage::Read => "read",
+      UserDataStage::Parse => "parse",
+      UserDataStage::Serialize => "serialize",
+      UserDataStage::Write => "write",
+      UserDataStage::Sign => "sign",
+      UserDataStage::Publish => "publish",
+    };
+    f.write_str(stage)
+  }
+}
+
+/// A json file in the user's data directory, data/users/{auth_address}/
+///
+/// ```ignore
+/// let data = UserData::<Data>::current("data.json").await?;
+/// data.update(|data| data.posts.push(post)).await?;
+/// ```
+pub struct UserData<T> {
+  inner_path:         InnerPath,
+  content_inner_path: InnerPath,
+  rollback:           bool,
+  check_rules:        bool,
+  data:               PhantomData<T>,
+}
+
+impl<T: Serialize + DeserializeOwned + Default> UserData<T> {
+  /// The file name may include subdirectories, like posts/1.json
+  pub fn new(auth_address: &AuthAddress, file_name: &str) -> Result<Self, Error> {
+    let directory = InnerPath::user_directory(auth_address)?;
+    Ok(UserData {
+      inner_path:         directory.join(file_name)?,
+      content_inner_path: directory.join("content.json")?,
+      rollback:           false,
+      check_rules:        false,
+      data:               PhantomData,
+    })
+  }
+
+  /// The file of the currently logged in user
+  pub async fn current(file_name: &str) -> Result<Self, Error> {
+    let auth_address = site_info()
+      .await?
+      .auth_address
+      .ok_or(Error::NoAuthAddress)?;
+    UserData::new(&auth_address, file_name)
+  }
+
+  /// Restore the previous file if signing fails
+  pub fn rollback(mut self, rollback: bool) -> Self {
+    self.rollback = rollback;
+    self
+  }
+
+  /// Check the new content against the fileRules before writing it
+  pub fn check_rules(mut self, check_rules: bool) -> Self {
+    self.check_rules = check_rules;
+    self
+  }
+
+  pub fn inner_path(&self) -> &InnerPath {
+    &self.inner_path
+  }
+
+  pub fn content_inner_path(&self) -> &InnerPath {
+    &self.content_inner_path
+  }
+
+  /// Read the file, a file that was never signed results in the default value
+  pub async fn read(&self) -> Result<T, Error> {
+    let content = self
+      .read_content()
+      .await
+      .map_err(|err| Self::stage_error(UserDataStage::Read, err))?;
+    Self::parse(&content)
+  }
+
+  /// Read, modify, write, sign and publish the file
+  /// Returns the data as written
+  pub async fn update<F: FnOnce(&mut T)>(&self, modify: F) -> Result<T, Error> {
+    let original = self
+      .read_content()
+      .await
+      .map_err(|err| Self::stage_error(UserDataStage::Read, err))?;
+    let mut data = Self::parse(&original)?;
+    modify(&mut data);
+
+    let content = serde_json::to_string_pretty(&data)
+      .map_err(|err| Self::stage_error(UserDataStage::Serialize, err.into()))?;
+    if self.check_rules {
+      file_rules_check(&self.inner_path, content.len() as u64)
+        .await
+        .map_err(|err| Self::stage_error(UserDataStage::Write, err))?;
+    }
+    file_write_string(&self.inner_path, content)
+      .await
+      .map_err(|err| Self::stage_error(UserDataStage::Write, err))?;
+
+    if let Err(err) = site_sign(None, Some(self.content_inner_path.clone()), false).await {
+      if self.rollback {
+        // The sign error explains the failure, a failed restore leaves the new file in place
+        let _ = self.restore(original).await;
+      }
+      return Err(Self::stage_error(UserDataStage::Sign, err));
+    }
+    site_publish(None, Some(self.content_inner_path.clone()), false)
+      .await
+      .map_err(|err| Self::stage_error(UserDataStage::Publish, err))?;
+    Ok(data)
+  }
+
+  /// None only if the file is not signed in its content.json,
+  /// a signed file that is not downloaded yet is fetched from peers
+  async fn read_content(&self) -> Result<Option<String>, Error> {
+    if let Some(content) = file_get_string(&self.inner_path, false, None).await? {
+      return Ok(Some(content));
+    }
+    let content: ContentJson = match file_get_string(&self.content_inner_path, false, None).await? {
+      Some(content) => content.parse()?,
+      None => return Ok(None),
+    };
+    let directory = self.content_inner_path.parent().unwrap_or_default();
+    let relative_path = self.inner_path.strip_prefix(&directory).unwrap_or_default();
+    if !content.files.contains_key(relative_path) {
+      return Ok(None);
+    }
+    match file_get_string(&self.inner_path, true, None).await? {
+      Some(content) => Ok(Some(content)),
+      None => Err(Error::FileNotDownloaded(self.inner_path.to_string())),
+    }
+  }
+
+  fn parse(content: &Option<String>) -> Result<T, Error> {
+    match content {
+      Some(content) => serde_json::from_str(content)
+        .map_err(|err| Self::stage_error(UserDataStage::Parse, err.into())),
+      None => Ok(T::default()),
+    }
+  }
+
+  async fn restore(&self, original: Option<String>) -> Result<(), Error> {
+    let restored = match original {
+      Some(original) => file_write_string(&self.inner_path, original).await,
+      None => file_delete(&self.inner_path).await,
+    };
+    restored.map_err(|err| Self::stage_error(UserDataStage::Write, err))
+  }
+
+  fn stage_error(stage: UserDataStage, source: Error) -> Error {
+    Error::UserData {
+      stage,
+      source: Box::new(source),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn nested_files_belong_to_the_user_content_json() {
+    let auth_address = AuthAddress::new("1HeLLo4uzjaLetFx6NH3PMwFP3qbRbTf3D").unwrap();
+    let data = UserData::<Vec<String>>::new(&auth_address, "posts/1.json").unwrap();
+    assert_eq!(
+      data.inner_path().as_str(),
+      "data/users/1HeLLo4uzjaLetFx6NH3PMwFP3qbRbTf3D/posts/1.json"
+    );
+    assert_eq!(
+      data.content_inner_path().as_str(),
+      "data/users/1HeLLo4uzjaLetFx6NH3PMwFP3qbRbTf3D/content.json"
+    );
+  }
+}
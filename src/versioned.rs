@@ -0,0 +1,134 @@
+use crate::content::{owning_content_json, ContentJson};
+use crate::inner_path::{InnerPath, IntoInnerPath};
+use crate::ui_server::{file_get_string, file_write_string};
+use crate::ZeroFrameError as Error;
+
+/// How often write_merging merges before giving up on a file that keeps changing
+pub const MAX_MERGE_ATTEMPTS: usize = 3;
+
+/// The signed state of a file, from the content.json in its directory
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FileVersion {
+  /// The modified time of the content.json
+  pub modified: f64,
+  /// None if the file is not signed yet
+  pub sha512:   Option<String>,
+}
+
+impl FileVersion {
+  /// The content.json was signed again with a different version of the file
+  pub fn conflicts_with(&self, base: &FileVersion) -> bool {
+    self.modified != base.modified && self.sha512 != base.sha512
+  }
+}
+
+/// A text file together with the signed version it was read at
+///
+/// ```ignore
+/// let file = file_get_versioned("data/users/1A.../data.json").await?;
+/// file
+///   .write_merging(content, |ours, theirs| merge_posts(ours, theirs))
+///   .await?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct VersionedFile {
+  pub inner_path: InnerPath,
+  pub content:    Option<String>,
+  pub version:    FileVersion,
+}
+
+/// Read the signed version of a file from the content.json that owns it,
+/// a missing content.json results in the default version
+pub async fn file_version<P: IntoInnerPath>(inner_path: P) -> Result<FileVersion, Error> {
+  let inner_path = inner_path.into_inner_path()?;
+  let content_inner_path = owning_content_json(&inner_path).await?;
+  let content: ContentJson = match file_get_string(&content_inner_path, false, None).await? {
+    Some(content) => content.parse()?,
+    None => return Ok(FileVersion::default()),
+  };
+  let directory = content_inner_path.parent().unwrap_or_default();
+  let relative_path = inner_path.strip_prefix(&directory).unwrap_or_default();
+  let sha512 = content
+    .files
+    .get(relative_path)
+    .or_else(|| content.files_optional.get(relative_path))
+    .map(|file| file.sha512.clone());
+  Ok(FileVersion {
+    modified: content.modified,
+    sha512,
+  })
+}
+
+/// Read a text file and the version it was signed at
+pub async fn file_get_versioned<P: IntoInnerPath>(inner_path: P) -> Result<VersionedFile, Error> {
+  let inner_path = inner_path.into_inner_path()?;
+  let version = file_version(&inner_path).await?;
+  let content = file_get_string(&inner_path, false, None).await?;
+  Ok(VersionedFile {
+    inner_path,
+    content,
+    version,
+  })
+}
+
+impl VersionedFile {
+  /// Write the file unless a different version was signed since it was read
+  /// Read the file again after signing, the new signature changes its version.
+  pub async fn write(&self, content: String) -> Result<(), Error> {
+    let current = file_version(&self.inner_path).await?;
+    if current.conflicts_with(&self.version) {
+      return Err(Error::WriteConflict {
+        inner_path: self.inner_path.to_string(),
+        expected:   self.version.modified,
+        found:      current.modified,
+      });
+    }
+    file_write_string(&self.inner_path, content).await
+  }
+
+  /// Write the file, merging with the newer version on a conflict
+  /// The merge callback receives our content and the content that was signed in the meantime.
+  pub async fn write_merging<F>(&self, content: String, mut merge: F) -> Result<(), Error>
+  where
+    F: FnMut(&str, Option<&str>) -> String,
+  {
+    let mut file = self.clone();
+    let mut content = content;
+    let mut attempts = 0;
+    loop {
+      match file.write(content.clone()).await {
+        Err(Error::WriteConflict { .. }) if attempts < MAX_MERGE_ATTEMPTS => {
+          attempts += 1;
+          file = file_get_versioned(&file.inner_path).await?;
+          content = merge(&content, file.content.as_deref());
+        }
+        result => return result,
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn version(modified: f64, sha512: Option<&str>) -> FileVersion {
+    FileVersion {
+      modified,
+      sha512: sha512.map(String::from),
+    }
+  }
+
+  #[test]
+  fn conflicts_with() {
+    let base = version(1.0, Some("a"));
+    assert!(!base.conflicts_with(&base));
+    // Another file of the content.json was signed
+    assert!(!version(2.0, Some("a")).conflicts_with(&base));
+    assert!(version(2.0, Some("b")).conflicts_with(&base));
+    assert!(version(2.0, None).conflicts_with(&base));
+    // The file was signed for the first time since it was read
+    assert!(version(2.0, Some("a")).conflicts_with(&FileVersion::default()));
+    assert!(!FileVersion::default().conflicts_with(&FileVersion::default()));
+  }
+}
@@ -1,5 +1,11 @@
 use super::{cmd, cmdp, on_request};
+use crate::events::on_site_info;
+use crate::responses::ZeroResponse;
 use crate::ZeroFrameError as Error;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::fmt;
+use std::str::FromStr;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::{closure::Closure};
 
@@ -81,9 +87,72 @@ pub fn open_window(url: &str, target: Option<String>, specs: Option<String>) {
   cmd("wrapperOpenWindow", params);
 }
 
-pub async fn permission_add(permission: &str) -> bool {
-  let res = cmdp("wrapperPermissionAdd", vec![JsValue::from_str(permission)]).await;
-  return res.as_string() == Some(String::from("ok"));
+/// Site permissions, as stored in SiteSettings::permissions
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(from = "String", into = "String")]
+pub enum Permission {
+  Admin,
+  NoSandbox,
+  Cors(String),
+  Merger(String),
+  Other(String),
+}
+
+impl fmt::Display for Permission {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Permission::Admin => f.write_str("ADMIN"),
+      Permission::NoSandbox => f.write_str("NOSANDBOX"),
+      Permission::Cors(address) => write!(f, "Cors:{}", address),
+      Permission::Merger(merged_type) => write!(f, "Merger:{}", merged_type),
+      Permission::Other(permission) => f.write_str(permission),
+    }
+  }
+}
+
+impl FromStr for Permission {
+  type Err = Infallible;
+
+  fn from_str(permission: &str) -> Result<Self, Self::Err> {
+    Ok(match permission {
+      "ADMIN" => Permission::Admin,
+      "NOSANDBOX" => Permission::NoSandbox,
+      _ => {
+        if let Some(address) = permission.strip_prefix("Cors:") {
+          Permission::Cors(address.to_string())
+        } else if let Some(merged_type) = permission.strip_prefix("Merger:") {
+          Permission::Merger(merged_type.to_string())
+        } else {
+          Permission::Other(permission.to_string())
+        }
+      }
+    })
+  }
+}
+
+impl From<String> for Permission {
+  fn from(permission: String) -> Self {
+    match permission.parse() {
+      Ok(permission) => permission,
+      Err(infallible) => match infallible {},
+    }
+  }
+}
+
+impl From<Permission> for String {
+  fn from(permission: Permission) -> Self {
+    permission.to_string()
+  }
+}
+
+/// Ask the user to grant a permission to the site
+pub async fn permission_add(permission: &Permission) -> Result<(), Error> {
+  let response = cmdp(
+    "wrapperPermissionAdd",
+    vec![JsValue::from_str(&permission.to_string())],
+  )
+  .await;
+  response.result()
 }
 
 pub enum PromptType {
@@ -145,10 +214,47 @@ pub fn set_viewport(viewport: &str) {
   cmd("wrapperSetViewport", vec![JsValue::from_str(viewport)])
 }
 
+/// Handle requests ZeroNet sends to the site, like setSiteInfo or websocketOpened
+/// ZeroFrame keeps one handler per command, a later handler replaces the previous one.
+/// setSiteInfo handlers are added with events::on_site_info, so they do not replace the crate's.
 pub fn add_request_handler<F: Fn(String, JsValue) + 'static>(cmd: &str, handler: F) {
+  if cmd == "setSiteInfo" {
+    on_site_info(handler);
+  } else {
+    register_request_handler(cmd, handler);
+  }
+}
+
+pub(crate) fn register_request_handler<F: Fn(String, JsValue) + 'static>(cmd: &str, handler: F) {
   let handler = Box::new(handler) as Box<dyn Fn(_, _)>;
   // let handler = Box::new(move |a, b| handler(a, b)) as Box<dyn Fn(_, _)>;
   let closure = Closure::wrap(handler);
   on_request(cmd, &closure);
   closure.forget();
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn permission_round_trip() {
+    let permissions = [
+      ("ADMIN", Permission::Admin),
+      ("NOSANDBOX", Permission::NoSandbox),
+      (
+        "Cors:1HeLLo4uzjaLetFx6NH3PMwFP3qbRbTf3D",
+        Permission::Cors("1HeLLo4uzjaLetFx6NH3PMwFP3qbRbTf3D".to_string()),
+      ),
+      ("Merger:ZeroMe", Permission::Merger("ZeroMe".to_string())),
+      ("BIGFILE", Permission::Other("BIGFILE".to_string())),
+    ];
+    for (text, permission) in permissions.iter() {
+      assert_eq!(&text.parse::<Permission>().unwrap(), permission);
+      assert_eq!(&permission.to_string(), text);
+      let json = format!("\"{}\"", text);
+      assert_eq!(&serde_json::from_str::<Permission>(&json).unwrap(), permission);
+      assert_eq!(serde_json::to_string(permission).unwrap(), json);
+    }
+  }
+}
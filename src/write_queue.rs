@@ -0,0 +1,313 @@
+use super::sleep;
+use crate::content::owning_content_json;
+use crate::events::site_info_stream;
+use crate::inner_path::{InnerPath, IntoInnerPath};
+use crate::responses::{Base64, SiteEvent, ZeroResponse};
+use crate::ui_server::{file_write_bytes, site_publish, site_sign};
+use crate::wrapper::{get_local_storage, set_local_storage};
+use crate::ZeroFrameError as Error;
+use futures::future::{self, Either};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+use wasm_bindgen::JsValue;
+
+/// Key of the queue in the site's local storage
+pub const STORAGE_KEY: &str = "zeroframe_write_queue";
+pub const DEFAULT_INITIAL_BACKOFF: usize = 1000;
+pub const DEFAULT_MAX_BACKOFF: usize = 5 * 60 * 1000;
+
+/// A file waiting to be written, signed and published
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PendingWrite {
+  pub inner_path: InnerPath,
+  pub content:    Base64,
+  /// Increased on every edit, so an edit during a flush is not dropped
+  pub revision:   u64,
+  /// The file is written, only signing and publishing are left
+  pub written:    bool,
+  pub attempts:   u32,
+  pub last_error: Option<String>,
+  /// Time of the last edit in ms since the epoch
+  pub queued_at:  f64,
+}
+
+/// A snapshot of the queue
+#[derive(Clone, Debug, Default)]
+pub struct WriteQueueState {
+  pub pending:  Vec<PendingWrite>,
+  pub flushing: bool,
+  /// Consecutive flushes that failed, resets when a flush succeeds
+  pub failures: u32,
+}
+
+#[derive(Default)]
+struct Inner {
+  entries:  BTreeMap<InnerPath, PendingWrite>,
+  revision: u64,
+  flushing: bool,
+  /// An edit was queued since the running flush took its snapshot
+  changed:  bool,
+  failures: u32,
+}
+
+/// Writes that survive failed publishes and reloads
+///
+/// Edits are persisted in the site's local storage before they are written.
+/// Multiple edits to a file are coalesced, and the files of a content.json
+/// are signed and published together.
+///
+/// ```ignore
+/// let queue = WriteQueue::load().await?;
+/// wasm_bindgen_futures::spawn_local(queue.clone().run());
+/// queue.enqueue_string("data/users/1A.../data.json", content).await?;
+/// ```
+#[derive(Clone)]
+pub struct WriteQueue {
+  inner:           Rc<RefCell<Inner>>,
+  initial_backoff: usize,
+  max_backoff:     usize,
+}
+
+impl WriteQueue {
+  /// Restore the queue from local storage
+  pub async fn load() -> Result<Self, Error> {
+    let storage = load_storage().await?;
+    let entries: Vec<PendingWrite> = match storage.get(STORAGE_KEY) {
+      Some(entries) => serde_json::from_value(entries.clone())?,
+      None => vec![],
+    };
+    let inner = Inner {
+      revision: entries
+        .iter()
+        .map(|entry| entry.revision)
+        .max()
+        .unwrap_or_default(),
+      entries:  entries
+        .into_iter()
+        .map(|entry| (entry.inner_path.clone(), entry))
+        .collect(),
+      flushing: false,
+      changed:  false,
+      failures: 0,
+    };
+    Ok(WriteQueue {
+      inner:           Rc::new(RefCell::new(inner)),
+      initial_backoff: DEFAULT_INITIAL_BACKOFF,
+      max_backoff:     DEFAULT_MAX_BACKOFF,
+    })
+  }
+
+  /// Wait this many ms after the first failure, doubling up to max_backoff
+  pub fn backoff(mut self, initial_backoff: usize, max_backoff: usize) -> Self {
+    self.initial_backoff = initial_backoff;
+    self.max_backoff = max_backoff;
+    self
+  }
+
+  pub fn state(&self) -> WriteQueueState {
+    let inner = self.inner.borrow();
+    WriteQueueState {
+      pending:  inner.entries.values().cloned().collect(),
+      flushing: inner.flushing,
+      failures: inner.failures,
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.inner.borrow().entries.is_empty()
+  }
+
+  /// Queue a text file and try to publish it right away
+  /// Only fails if the edit could not be persisted, publish errors are kept in the state
+  pub async fn enqueue_string<P: IntoInnerPath>(
+    &self,
+    inner_path: P,
+    content: String,
+  ) -> Result<(), Error> {
+    self.enqueue_bytes(inner_path, content.into_bytes()).await
+  }
+
+  /// Queue a binary file and try to publish it right away
+  pub async fn enqueue_bytes<P: IntoInnerPath>(
+    &self,
+    inner_path: P,
+    content: Vec<u8>,
+  ) -> Result<(), Error> {
+    let inner_path = inner_path.into_inner_path()?;
+    {
+      let mut inner = self.inner.borrow_mut();
+      inner.revision += 1;
+      inner.changed = true;
+      let entry = PendingWrite {
+        inner_path: inner_path.clone(),
+        content:    Base64::encode(content),
+        revision:   inner.revision,
+        written:    false,
+        attempts:   0,
+        last_error: None,
+        queued_at:  js_sys::Date::now(),
+      };
+      inner.entries.insert(inner_path, entry);
+    }
+    self.persist().await?;
+    let _ = self.flush().await;
+    Ok(())
+  }
+
+  /// Write, sign and publish everything in the queue
+  /// Edits queued during a flush are flushed by it as well.
+  /// Returns the first error, the entries that failed stay queued
+  pub async fn flush(&self) -> Result<(), Error> {
+    {
+      let mut inner = self.inner.borrow_mut();
+      if inner.flushing {
+        return Ok(());
+      }
+      inner.flushing = true;
+    }
+    let result = loop {
+      let result = self.flush_entries().await;
+      if result.is_err() || !self.inner.borrow().changed {
+        break result;
+      }
+    };
+
+    {
+      let mut inner = self.inner.borrow_mut();
+      inner.flushing = false;
+      match result {
+        Ok(_) => inner.failures = 0,
+        Err(_) => inner.failures += 1,
+      }
+    }
+    self.persist().await?;
+    result
+  }
+
+  /// Retry until the queue is empty, with backoff and whenever peers are found
+  /// Spawn it with wasm_bindgen_futures::spawn_local, it runs for the lifetime of the page
+  pub async fn run(self) {
+    let mut updates = site_info_stream();
+    loop {
+      if !self.is_empty() {
+        let _ = self.flush().await;
+      }
+      let delay = self.next_delay();
+      let peers_added = async {
+        while let Some(site_info) = updates.next().await {
+          if let Some(SiteEvent::PeersAdded(_)) = site_info.event {
+            return true;
+          }
+        }
+        false
+      };
+      let timeout = Box::pin(sleep(delay as f64));
+      if let Either::Left((false, _)) = future::select(Box::pin(peers_added), timeout).await {
+        return;
+      }
+    }
+  }
+
+  /// Flush a snapshot of the queue, grouped by the content.json that signs the files
+  async fn flush_entries(&self) -> Result<(), Error> {
+    let entries: Vec<PendingWrite> = {
+      let mut inner = self.inner.borrow_mut();
+      inner.changed = false;
+      inner.entries.values().cloned().collect()
+    };
+    let mut groups: BTreeMap<InnerPath, Vec<PendingWrite>> = BTreeMap::new();
+    for entry in entries {
+      let content_inner_path = owning_content_json(&entry.inner_path).await?;
+      groups.entry(content_inner_path).or_default().push(entry);
+    }
+
+    let mut result = Ok(());
+    for (content_inner_path, entries) in groups {
+      if let Err(err) = self.flush_group(&content_inner_path, &entries).await {
+        self.record_failure(&entries, &err);
+        if result.is_ok() {
+          result = Err(err);
+        }
+      }
+    }
+    result
+  }
+
+  async fn flush_group(
+    &self,
+    content_inner_path: &InnerPath,
+    entries: &[PendingWrite],
+  ) -> Result<(), Error> {
+    for entry in entries.iter().filter(|entry| !entry.written) {
+      file_write_bytes(&entry.inner_path, entry.content.decode()?).await?;
+      self.update_entry(entry, |stored| stored.written = true);
+    }
+    site_sign(None, Some(content_inner_path.clone()), false).await?;
+    site_publish(None, Some(content_inner_path.clone()), false).await?;
+
+    let mut inner = self.inner.borrow_mut();
+    for entry in entries {
+      let unchanged = match inner.entries.get(&entry.inner_path) {
+        Some(stored) => stored.revision == entry.revision,
+        None => false,
+      };
+      if unchanged {
+        inner.entries.remove(&entry.inner_path);
+      }
+    }
+    Ok(())
+  }
+
+  /// Update the stored entry, unless it was edited again in the meantime
+  fn update_entry<F: FnOnce(&mut PendingWrite)>(&self, entry: &PendingWrite, update: F) {
+    let mut inner = self.inner.borrow_mut();
+    if let Some(stored) = inner.entries.get_mut(&entry.inner_path) {
+      if stored.revision == entry.revision {
+        update(stored);
+      }
+    }
+  }
+
+  fn record_failure(&self, entries: &[PendingWrite], err: &Error) {
+    let paths: BTreeSet<&InnerPath> = entries.iter().map(|entry| &entry.inner_path).collect();
+    let mut inner = self.inner.borrow_mut();
+    for stored in inner.entries.values_mut() {
+      if paths.contains(&stored.inner_path) {
+        stored.attempts += 1;
+        stored.last_error = Some(err.to_string());
+      }
+    }
+  }
+
+  fn next_delay(&self) -> usize {
+    let failures = self.inner.borrow().failures;
+    if failures == 0 {
+      return self.max_backoff;
+    }
+    let factor = 2usize.saturating_pow(failures - 1);
+    self
+      .initial_backoff
+      .saturating_mul(factor)
+      .min(self.max_backoff)
+  }
+
+  async fn persist(&self) -> Result<(), Error> {
+    let entries: Vec<PendingWrite> = self.inner.borrow().entries.values().cloned().collect();
+    let mut storage = load_storage().await?;
+    storage.insert(STORAGE_KEY.to_string(), serde_json::to_value(entries)?);
+    set_local_storage(JsValue::from_serde(&storage)?);
+    Ok(())
+  }
+}
+
+/// The site's local storage, other keys are kept as they are
+async fn load_storage() -> Result<Map<String, Value>, Error> {
+  let storage = get_local_storage()
+    .await
+    .response::<Option<Map<String, Value>>>()?;
+  Ok(storage.unwrap_or_default())
+}